@@ -0,0 +1,8 @@
+create table api_keys (
+    id int generated always as identity primary key,
+    name text not null,
+    key_hash text not null unique,
+    scope text not null check (scope in ('read', 'write', 'admin')),
+    created_at timestamptz not null default now(),
+    revoked_at timestamptz
+);
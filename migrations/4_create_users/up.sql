@@ -0,0 +1,16 @@
+create table users (
+    id int generated always as identity primary key,
+    username text not null unique,
+    password_hash text not null,
+    scope text not null default 'write' check (scope in ('read', 'write', 'admin')),
+    created_at timestamptz not null default now()
+);
+
+create table sessions (
+    token_hash text primary key,
+    user_id int references users(id) on delete cascade not null,
+    created_at timestamptz not null default now(),
+    expires_at timestamptz not null
+);
+
+create index sessions_user_id_index ON sessions USING btree(user_id);
@@ -0,0 +1,2 @@
+drop index stories_owner_id_index;
+alter table stories drop column owner;
@@ -0,0 +1,3 @@
+alter table stories add column owner text;
+
+create index stories_owner_id_index ON stories USING btree(owner, id);
@@ -0,0 +1 @@
+drop table story_members;
@@ -0,0 +1,9 @@
+create table story_members (
+    story_id int references stories(id) on delete cascade not null,
+    member text not null,
+    role text not null check (role in ('viewer', 'editor')),
+    created_at timestamptz not null default now(),
+    primary key (story_id, member)
+);
+
+create index story_members_member_story_id_index ON story_members USING btree(member, story_id);
@@ -0,0 +1,12 @@
+local counter = 0
+
+-- Story ids to spread writes over; match the number of seeded stories
+local max_story_id = tonumber(os.getenv("MAX_STORY_ID") or "10000")
+
+request = function()
+    counter = counter + 1
+    headers = {}
+    headers["Content-Type"] = "application/json"
+    body = '{"name": "Story ' .. counter .. '"}'
+    return wrk.format("PATCH", "/stories/" .. math.random(1, max_story_id), headers, body)
+end
@@ -0,0 +1,115 @@
+use crate::{
+    api::{
+        auth::{session_cookie, SESSION_COOKIE},
+        dto::CredentialsBody,
+        password, Ctx,
+    },
+    config::Config,
+    Error, Result,
+};
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::post,
+    Json, Router,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// API routes for user accounts and sessions
+pub fn routes() -> Router<Arc<Ctx>> {
+    Router::new()
+        .route("/auth/register", post(register))
+        .route("/auth/login", post(login))
+        .route("/auth/logout", post(logout))
+        .route("/auth/refresh", post(refresh))
+}
+
+/// Create a user account
+async fn register(
+    State(ctx): State<Arc<Ctx>>,
+    Json(body): Json<CredentialsBody>,
+) -> Result<impl IntoResponse> {
+    tracing::info!("POST /auth/register");
+    tracing::debug!("body = {:?}", body);
+    let (username, password) = body.validate()?;
+    let password_hash = password::hash(password).await?;
+    let user = ctx.repo.insert_user(username, password_hash).await?;
+    Ok((StatusCode::CREATED, Json(user)))
+}
+
+/// Log in with a username and password, starting a session kept in a cookie
+async fn login(
+    peer: Option<ConnectInfo<SocketAddr>>,
+    State(ctx): State<Arc<Ctx>>,
+    Json(body): Json<CredentialsBody>,
+) -> Result<impl IntoResponse> {
+    tracing::info!("POST /auth/login");
+    tracing::debug!("body = {:?}", body);
+    let (username, password) = body.validate()?;
+    let ip = peer.map(|ConnectInfo(addr)| addr.ip());
+
+    ctx.login_throttle.attempt(&username, ip)?;
+    let credentials = ctx.repo.select_user_credentials(&username).await?;
+    let (user, stored) = credentials.unzip();
+    if !password::verify(password, stored).await? {
+        return Err(Error::unauthorized("invalid username or password"));
+    }
+    ctx.login_throttle.succeeded(&username, ip);
+
+    // Verified, so the user exists
+    let user = user.ok_or_else(|| Error::internal("verified unknown user".into()))?;
+    let ttl = Duration::from_secs(ctx.config.session_ttl_secs);
+    let token = ctx.repo.insert_session(user.id, ttl).await?;
+    Ok((
+        [(header::SET_COOKIE, cookie(&ctx.config, &token, ttl))],
+        Json(user),
+    ))
+}
+
+/// End the current session, if any
+async fn logout(headers: HeaderMap, State(ctx): State<Arc<Ctx>>) -> Result<impl IntoResponse> {
+    tracing::info!("POST /auth/logout");
+    if let Some(token) = session_cookie(&headers) {
+        ctx.repo.delete_session(token).await?;
+    }
+    Ok((
+        StatusCode::NO_CONTENT,
+        [(header::SET_COOKIE, cookie(&ctx.config, "", Duration::ZERO))],
+    ))
+}
+
+/// Swap the current session for a new token with a later expiry
+async fn refresh(headers: HeaderMap, State(ctx): State<Arc<Ctx>>) -> Result<impl IntoResponse> {
+    tracing::info!("POST /auth/refresh");
+    let token = session_cookie(&headers).ok_or_else(|| Error::unauthorized("missing session"))?;
+    let ttl = Duration::from_secs(ctx.config.session_ttl_secs);
+    let token = ctx
+        .repo
+        .refresh_session(token, ttl)
+        .await?
+        .ok_or_else(|| Error::unauthorized("invalid session"))?;
+    Ok((
+        StatusCode::NO_CONTENT,
+        [(header::SET_COOKIE, cookie(&ctx.config, &token, ttl))],
+    ))
+}
+
+/// A session cookie, hidden from scripts and never sent cross-site.
+/// An empty token with no lifetime clears the cookie.
+fn cookie(config: &Config, token: &str, ttl: Duration) -> String {
+    let secure = if config.session_cookie_secure {
+        "; Secure"
+    } else {
+        ""
+    };
+    format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Strict{}",
+        SESSION_COOKIE,
+        token,
+        ttl.as_secs(),
+        secure
+    )
+}
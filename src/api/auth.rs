@@ -0,0 +1,138 @@
+use super::Ctx;
+use crate::{
+    domain::{Principal, Scope},
+    Error,
+};
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap},
+};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// Header carrying an api key; `Authorization: Bearer` accepts an api key or a JWT.
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Cookie carrying a session token from a password login.
+pub const SESSION_COOKIE: &str = "session";
+
+/// The scope a route requires, named by a marker type.
+pub trait RequiredScope {
+    const SCOPE: Scope;
+}
+
+/// Marker for routes that read.
+pub struct ReadScope;
+
+/// Marker for routes that write.
+pub struct WriteScope;
+
+/// Marker for operational routes.
+pub struct AdminScope;
+
+impl RequiredScope for ReadScope {
+    const SCOPE: Scope = Scope::Read;
+}
+
+impl RequiredScope for WriteScope {
+    const SCOPE: Scope = Scope::Write;
+}
+
+impl RequiredScope for AdminScope {
+    const SCOPE: Scope = Scope::Admin;
+}
+
+/// Extracts the authenticated caller, rejecting missing or invalid credentials with 401
+/// and callers without the route's scope with 403.
+/// Every caller is allowed when authentication is disabled.
+/// The caller is kept in request extensions, so later extractors reuse it.
+pub struct Auth<S> {
+    pub principal: Principal,
+    scope: PhantomData<S>,
+}
+
+#[async_trait]
+impl<S: RequiredScope> FromRequestParts<Arc<Ctx>> for Auth<S> {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, ctx: &Arc<Ctx>) -> Result<Self, Error> {
+        let principal = match parts.extensions.get::<Principal>() {
+            Some(principal) => principal.clone(),
+            None => {
+                let principal = authenticate(parts, ctx).await?;
+                parts.extensions.insert(principal.clone());
+                principal
+            }
+        };
+        if !principal.allows(S::SCOPE) {
+            return Err(Error::forbidden(format!("requires {} scope", S::SCOPE)));
+        }
+        Ok(Self {
+            principal,
+            scope: PhantomData,
+        })
+    }
+}
+
+/// Find the caller for the credentials sent with a request.
+async fn authenticate(parts: &Parts, ctx: &Ctx) -> Result<Principal, Error> {
+    if !ctx.config.auth_enabled {
+        return Ok(Principal::anonymous());
+    }
+    match credential(parts).ok_or_else(|| Error::unauthorized("missing credentials"))? {
+        Credential::Bearer(token) if is_jwt(token) => match ctx.jwks.as_ref() {
+            Some(jwks) => jwks.verify(token).await,
+            None => Err(Error::unauthorized("bearer tokens are not accepted")),
+        },
+        Credential::ApiKey(key) | Credential::Bearer(key) => ctx
+            .repo
+            .authenticate_key(key)
+            .await?
+            .ok_or_else(|| Error::unauthorized("invalid api key")),
+        Credential::Session(token) => ctx
+            .repo
+            .authenticate_session(token)
+            .await?
+            .ok_or_else(|| Error::unauthorized("invalid session")),
+    }
+}
+
+/// Credentials sent with a request.
+enum Credential<'a> {
+    ApiKey(&'a str),
+    Bearer(&'a str),
+    Session(&'a str),
+}
+
+/// The api key header, else a bearer token, else a session cookie.
+fn credential(parts: &Parts) -> Option<Credential<'_>> {
+    if let Some(value) = parts.headers.get(API_KEY_HEADER) {
+        return value.to_str().ok().map(Credential::ApiKey);
+    }
+    if let Some(value) = parts.headers.get(header::AUTHORIZATION) {
+        let (scheme, token) = value.to_str().ok()?.split_once(' ')?;
+        return scheme
+            .eq_ignore_ascii_case("bearer")
+            .then(|| Credential::Bearer(token.trim()));
+    }
+    session_cookie(&parts.headers).map(Credential::Session)
+}
+
+/// The session token from the request cookies.
+pub fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, token)| token)
+        .filter(|token| !token.is_empty())
+}
+
+/// Whether a bearer token is a JWT rather than an api key; api keys never contain dots.
+fn is_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
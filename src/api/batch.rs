@@ -0,0 +1,291 @@
+use crate::{
+    api::{
+        auth::{Auth, WriteScope},
+        dto::{BatchOp, BatchOpBody, BatchResultDto, IdRef, PatchTaskBody},
+        Ctx,
+    },
+    domain::{Principal, Role, Task},
+    repo::UnitOfWork,
+    Error, Result,
+};
+use axum::{extract::State, response::IntoResponse, routing::post, Json, Router};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// The kind of row a batch ref names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RefKind {
+    Story,
+    Task,
+}
+
+impl fmt::Display for RefKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefKind::Story => write!(f, "story"),
+            RefKind::Task => write!(f, "task"),
+        }
+    }
+}
+
+/// API routes for batches
+pub fn routes() -> Router<Arc<Ctx>> {
+    Router::new().route("/batch", post(run_batch))
+}
+
+/// Run a list of operations in a single transaction
+async fn run_batch(
+    auth: Auth<WriteScope>,
+    State(ctx): State<Arc<Ctx>>,
+    Json(body): Json<Vec<BatchOpBody>>,
+) -> Result<impl IntoResponse> {
+    tracing::info!("POST /batch");
+    tracing::debug!("body = {:?}", body);
+
+    let ops = BatchOpBody::validate_all(body)?;
+
+    let uow = ctx.repo.begin().await?;
+    let mut ids = HashMap::new();
+    let mut results = Vec::with_capacity(ops.len());
+
+    for (i, op) in ops.into_iter().enumerate() {
+        match run_op(&uow, &auth.principal, &mut ids, op).await {
+            Ok(result) => results.push(result),
+            Err(err) => {
+                uow.rollback().await?;
+                return Err(at_index(i, err));
+            }
+        }
+    }
+    uow.commit().await?;
+
+    Ok(Json(results))
+}
+
+/// Run a single batch operation, recording ids and kinds of created rows under their ref.
+async fn run_op(
+    uow: &UnitOfWork,
+    principal: &Principal,
+    ids: &mut HashMap<String, (RefKind, i32)>,
+    op: BatchOp,
+) -> Result<BatchResultDto> {
+    let result = match op {
+        BatchOp::CreateStory { reference, name } => {
+            let story = uow.insert_story(name, principal.subject.clone()).await?;
+            if let Some(r) = reference {
+                ids.insert(r, (RefKind::Story, story.id));
+            }
+            BatchResultDto::Story(story)
+        }
+        BatchOp::CreateTask {
+            reference,
+            story_id,
+            name,
+        } => {
+            let story_id = resolve(ids, &story_id, RefKind::Story)?;
+            check_story(uow, principal, story_id, Role::Editor).await?;
+            let task = uow
+                .insert_task(story_id, name, principal.owner_filter())
+                .await?;
+            if let Some(r) = reference {
+                ids.insert(r, (RefKind::Task, task.id));
+            }
+            BatchResultDto::Task(task)
+        }
+        BatchOp::UpdateStory { id, name } => {
+            let id = resolve(ids, &id, RefKind::Story)?;
+            check_story(uow, principal, id, Role::Editor).await?;
+            let editor = principal.owner_filter();
+            BatchResultDto::Story(uow.update_story(id, name, editor).await?)
+        }
+        BatchOp::UpdateTask { id, name, status } => {
+            let id = resolve(ids, &id, RefKind::Task)?;
+            let existing_task = select_task(uow, principal, id).await?;
+            let patch = PatchTaskBody {
+                name,
+                status: status.map(|s| s.to_string()),
+            };
+            let (name, status) = patch.validate(existing_task)?;
+            BatchResultDto::Task(uow.update_task(id, name, status).await?)
+        }
+        BatchOp::DeleteStory { id } => {
+            let id = resolve(ids, &id, RefKind::Story)?;
+            check_story(uow, principal, id, Role::Owner).await?;
+            match uow.delete_story(id).await? {
+                0 => return Err(Error::not_found(format!("story not found: {}", id))),
+                n => BatchResultDto::Deleted(n),
+            }
+        }
+        BatchOp::DeleteTask { id } => {
+            let id = resolve(ids, &id, RefKind::Task)?;
+            select_task(uow, principal, id).await?;
+            match uow.delete_task(id).await? {
+                0 => return Err(Error::not_found(format!("task not found: {}", id))),
+                n => BatchResultDto::Deleted(n),
+            }
+        }
+    };
+    Ok(result)
+}
+
+/// Check that the caller's role on a story allows an action, as not found when they
+/// have no role and forbidden when it's too low.
+async fn check_story(uow: &UnitOfWork, principal: &Principal, id: i32, needed: Role) -> Result<()> {
+    if principal.sees_all() {
+        return Ok(());
+    }
+    let story = uow.select_story(id).await?;
+    let role = if principal.owns(&story) {
+        Some(Role::Owner)
+    } else {
+        uow.select_member_role(id, &principal.subject).await?
+    };
+    Role::require(role, needed, id)
+}
+
+/// Select a task the caller may edit; tasks in stories they have no role on are not found.
+async fn select_task(uow: &UnitOfWork, principal: &Principal, id: i32) -> Result<Task> {
+    let task = uow.select_task(id).await?;
+    match check_story(uow, principal, task.story_id, Role::Editor).await {
+        Err(Error::NotFound { .. }) => Err(Error::not_found(format!("task not found: {}", id))),
+        result => result.map(|_| task),
+    }
+}
+
+/// Look up the id for a literal id or a ref created earlier in the batch, which must name
+/// a row of the expected kind.
+fn resolve(ids: &HashMap<String, (RefKind, i32)>, id: &IdRef, expected: RefKind) -> Result<i32> {
+    let r = match id {
+        IdRef::Id(id) => return Ok(*id),
+        IdRef::Ref(r) => r,
+    };
+    match r.strip_prefix('$').and_then(|name| ids.get(name).copied()) {
+        Some((kind, id)) if kind == expected => Ok(id),
+        Some((kind, _)) => Err(Error::invalid_args(&format!(
+            "ref {} is a {}, expected a {}",
+            r, kind, expected
+        ))),
+        None => Err(Error::invalid_args(&format!("unknown ref {}", r))),
+    }
+}
+
+/// Prefix error messages with the index of the failed operation.
+fn at_index(i: usize, err: Error) -> Error {
+    match err {
+        Error::InvalidArgs { messages } => Error::InvalidArgs {
+            messages: messages
+                .into_iter()
+                .map(|m| format!("[{}]: {}", i, m))
+                .collect(),
+        },
+        Error::NotFound { message } => Error::not_found(format!("[{}]: {}", i, message)),
+        Error::Internal { message } => Error::internal(format!("[{}]: {}", i, message)),
+        Error::TooLarge { message } => Error::TooLarge {
+            message: format!("[{}]: {}", i, message),
+        },
+        Error::Conflict { message } => Error::conflict(format!("[{}]: {}", i, message)),
+        Error::Forbidden { message } => Error::forbidden(format!("[{}]: {}", i, message)),
+        // Not caused by a single operation
+        Error::Unauthorized { .. } | Error::TooManyRequests { .. } => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn validate(ops: serde_json::Value) -> Result<Vec<BatchOp>> {
+        BatchOpBody::validate_all(serde_json::from_value(ops).unwrap())
+    }
+
+    fn messages(result: Result<impl fmt::Debug>) -> Vec<String> {
+        match result {
+            Err(Error::InvalidArgs { messages }) => messages,
+            other => panic!("expected invalid args, got {:?}", other),
+        }
+    }
+
+    fn created(refs: &[(&str, RefKind, i32)]) -> HashMap<String, (RefKind, i32)> {
+        refs.iter()
+            .map(|(name, kind, id)| (name.to_string(), (*kind, *id)))
+            .collect()
+    }
+
+    #[test]
+    fn refs_to_earlier_operations_are_valid() {
+        let ops = validate(json!([
+            {"op": "create_story", "ref": "s", "name": "story"},
+            {"op": "create_task", "ref": "t", "story_id": "$s", "name": "task"},
+            {"op": "update_task", "id": "$t", "status": "complete"},
+        ]));
+        assert_eq!(ops.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn forward_refs_are_unknown() {
+        let result = validate(json!([
+            {"op": "create_task", "story_id": "$s", "name": "task"},
+            {"op": "create_story", "ref": "s", "name": "story"},
+        ]));
+        assert_eq!(messages(result), ["[0].story_id: unknown ref $s"]);
+    }
+
+    #[test]
+    fn unknown_refs_and_ids_out_of_range_are_invalid() {
+        let result = validate(json!([
+            {"op": "create_story", "ref": "s", "name": "story"},
+            {"op": "delete_story", "id": "s"},
+            {"op": "delete_task", "id": "$t"},
+            {"op": "delete_task", "id": 0},
+        ]));
+        assert_eq!(
+            messages(result),
+            [
+                "[1].id: unknown ref s",
+                "[2].id: unknown ref $t",
+                "[3].id: must be > 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_finds_literal_ids_and_refs_of_the_expected_kind() {
+        let ids = created(&[("s", RefKind::Story, 7), ("t", RefKind::Task, 9)]);
+        assert_eq!(resolve(&ids, &IdRef::Id(3), RefKind::Task).unwrap(), 3);
+        let r = IdRef::Ref("$s".into());
+        assert_eq!(resolve(&ids, &r, RefKind::Story).unwrap(), 7);
+        let r = IdRef::Ref("$t".into());
+        assert_eq!(resolve(&ids, &r, RefKind::Task).unwrap(), 9);
+    }
+
+    #[test]
+    fn resolve_rejects_a_ref_of_the_wrong_kind() {
+        let ids = created(&[("t", RefKind::Task, 9)]);
+        let result = resolve(&ids, &IdRef::Ref("$t".into()), RefKind::Story);
+        assert_eq!(messages(result), ["ref $t is a task, expected a story"]);
+    }
+
+    #[test]
+    fn resolve_rejects_an_unknown_ref() {
+        let ids = created(&[("s", RefKind::Story, 7)]);
+        let result = resolve(&ids, &IdRef::Ref("$x".into()), RefKind::Story);
+        assert_eq!(messages(result), ["unknown ref $x"]);
+    }
+
+    #[test]
+    fn errors_are_prefixed_with_the_operation_index() {
+        let err = at_index(2, Error::invalid_args("ref $t is a task, expected a story"));
+        assert_eq!(
+            messages(Err::<(), _>(err)),
+            ["[2]: ref $t is a task, expected a story"]
+        );
+        match at_index(4, Error::not_found("story not found: 7".into())) {
+            Error::NotFound { message } => assert_eq!(message, "[4]: story not found: 7"),
+            other => panic!("expected not found, got {:?}", other),
+        }
+        let err = at_index(1, Error::unauthorized("invalid token"));
+        assert!(matches!(err, Error::Unauthorized { .. }));
+    }
+}
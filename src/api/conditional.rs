@@ -0,0 +1,158 @@
+use super::Ctx;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// Routes returning pages of stories or tasks.
+const LIST_ROUTES: &[&str] = &["/stories", "/stories/shared", "/stories/:id/tasks"];
+
+/// Routes returning a single story or task.
+const DETAIL_ROUTES: &[&str] = &["/stories/:id", "/tasks/:id"];
+
+/// Request headers carrying credentials. Responses depend on the caller, so shared caches
+/// must key on these even when Cache-Control allows them to store responses.
+const VARY_CREDENTIALS: &str = "authorization, cookie, x-api-key";
+
+/// Add weak ETags and Cache-Control to story and task reads, answering 304 when the
+/// client's copy is current. Handlers may set their own ETag; otherwise the body is hashed.
+/// Tags are weak because compression happens outside this layer, so gzip, br and identity
+/// representations share a tag; they are equivalent but not byte-identical.
+/// There are no row timestamps, so Last-Modified is never sent and If-Modified-Since is ignored.
+pub async fn conditional_get(State(ctx): State<Arc<Ctx>>, req: Request, next: Next) -> Response {
+    let is_read = matches!(*req.method(), Method::GET | Method::HEAD);
+    let path = req.extensions().get::<MatchedPath>().map(|p| p.as_str());
+    let cache_control = match path {
+        Some(p) if is_read && LIST_ROUTES.contains(&p) => &ctx.config.cache_control_list,
+        Some(p) if is_read && DETAIL_ROUTES.contains(&p) => &ctx.config.cache_control_detail,
+        _ => return next.run(req).await,
+    };
+    let if_none_match = req.headers().get(header::IF_NONE_MATCH).cloned();
+
+    let response = next.run(req).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    // Buffer the body to hash it
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            tracing::error!("failed to buffer response body: {}", err);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let etag = match parts.headers.get(header::ETAG) {
+        Some(etag) => etag.clone(),
+        None => etag(&bytes),
+    };
+
+    if let Ok(value) = HeaderValue::from_str(cache_control) {
+        parts.headers.insert(header::CACHE_CONTROL, value);
+    }
+    parts.headers.insert(header::ETAG, etag.clone());
+    parts
+        .headers
+        .append(header::VARY, HeaderValue::from_static(VARY_CREDENTIALS));
+
+    if if_none_match.is_some_and(|v| matches(&v, &etag)) {
+        return not_modified(&parts.headers);
+    }
+
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// An empty 304 keeping the validator and caching headers of the full response.
+fn not_modified(full: &HeaderMap) -> Response {
+    let mut headers = HeaderMap::new();
+    for name in [header::ETAG, header::CACHE_CONTROL, header::VARY] {
+        for value in full.get_all(&name) {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+    (StatusCode::NOT_MODIFIED, headers).into_response()
+}
+
+/// A weak entity tag from the rows in a response, for bodies with parts that vary between
+/// identical responses, like page tokens.
+pub fn content_etag<T: Serialize>(content: &T) -> HeaderValue {
+    etag(&serde_json::to_vec(content).unwrap_or_default())
+}
+
+/// A weak entity tag from a hash of the response body.
+fn etag(body: &[u8]) -> HeaderValue {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    HeaderValue::from_str(&format!("W/\"{}\"", hex)).expect("hex etag is a valid header")
+}
+
+/// Whether an If-None-Match header matches an etag, using weak comparison as GET requires.
+fn matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let Ok(tags) = if_none_match.to_str() else {
+        return false;
+    };
+    let etag = etag.to_str().unwrap_or_default().trim_start_matches("W/");
+    tags.split(',')
+        .map(|t| t.trim())
+        .any(|t| t == "*" || t.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(if_none_match: &'static str, etag: &'static str) -> bool {
+        super::matches(
+            &HeaderValue::from_static(if_none_match),
+            &HeaderValue::from_static(etag),
+        )
+    }
+
+    #[test]
+    fn a_wildcard_matches_any_tag() {
+        assert!(matches("*", "W/\"abc\""));
+    }
+
+    #[test]
+    fn any_tag_in_a_list_matches() {
+        assert!(matches("\"x\", W/\"abc\" ,\"y\"", "W/\"abc\""));
+        assert!(!matches("\"x\", \"y\"", "W/\"abc\""));
+    }
+
+    #[test]
+    fn weak_comparison_ignores_the_weak_prefix() {
+        assert!(matches("\"abc\"", "W/\"abc\""));
+        assert!(matches("W/\"abc\"", "\"abc\""));
+        assert!(!matches("W/\"abd\"", "W/\"abc\""));
+    }
+
+    #[tokio::test]
+    async fn not_modified_has_no_body_and_keeps_etag_and_vary() {
+        let mut full = HeaderMap::new();
+        full.insert(header::ETAG, HeaderValue::from_static("W/\"abc\""));
+        full.insert(header::CACHE_CONTROL, HeaderValue::from_static("private"));
+        full.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        full.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        full.append(header::VARY, HeaderValue::from_static(VARY_CREDENTIALS));
+
+        let response = not_modified(&full);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        let headers = response.headers();
+        assert_eq!(headers[header::ETAG], "W/\"abc\"");
+        assert_eq!(headers[header::CACHE_CONTROL], "private");
+        assert_eq!(headers.get_all(header::VARY).iter().count(), 2);
+        assert!(headers.get(header::CONTENT_TYPE).is_none());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.is_empty());
+    }
+}
@@ -0,0 +1,38 @@
+use crate::config::Config;
+use axum::http::{HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// Build the CORS layer from config; `None` when no origins are allowed.
+pub fn layer(config: &Config) -> Option<CorsLayer> {
+    if config.cors_allowed_origins.is_empty() {
+        return None;
+    }
+
+    let origins = if config.cors_allowed_origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(parse_all::<HeaderValue>(&config.cors_allowed_origins))
+    };
+
+    let layer = CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(parse_all::<Method>(&config.cors_allowed_methods))
+        .allow_headers(parse_all::<HeaderName>(&config.cors_allowed_headers))
+        .expose_headers(parse_all::<HeaderName>(&config.cors_expose_headers))
+        .allow_credentials(config.cors_allow_credentials)
+        .max_age(Duration::from_secs(config.cors_max_age_secs));
+
+    Some(layer)
+}
+
+/// Parse config values, panicking on invalid ones like the rest of config loading.
+fn parse_all<T: std::str::FromStr>(values: &[String]) -> Vec<T> {
+    values
+        .iter()
+        .map(|v| {
+            v.parse()
+                .unwrap_or_else(|_| panic!("invalid CORS config value: {}", v))
+        })
+        .collect()
+}
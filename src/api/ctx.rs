@@ -1,22 +1,74 @@
 use crate::{
+    api::{jwt::Jwks, throttle::LoginThrottle},
     config::Config,
     db::pool::{PgPool, PgPoolBuilder},
-    repo::Repo,
+    repo::{ReadCache, Replica, Repo, WriteBatcher},
     Result,
 };
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Repo, drivers, and use-cases for use in API routes.
 #[derive(Clone)]
 pub struct Ctx {
+    pub config: Arc<Config>,
     pub repo: Arc<Repo>,
+    pub jwks: Option<Arc<Jwks>>,
+    pub login_throttle: Arc<LoginThrottle>,
 }
 
 impl Ctx {
     /// Initialize repo, drivers, and use-cases from config.
     pub async fn init_from_config(config: Arc<Config>) -> Result<Self> {
-        let pool: PgPool = PgPoolBuilder::build(&config.db_url, config.db_max_pool_size).await?;
-        let repo = Arc::new(Repo::new(pool));
-        Ok(Self { repo })
+        let pool: PgPool = PgPoolBuilder::build(&config).await?;
+        let mut repo = Repo::new(pool.clone());
+
+        // Optionally cache hot reads, kept coherent across instances with LISTEN/NOTIFY
+        if config.read_cache_capacity > 0 {
+            let ttl = Duration::from_secs(config.read_cache_ttl_secs);
+            let mut cache = ReadCache::new(config.read_cache_capacity, ttl);
+            if config.read_cache_notify {
+                cache = cache.with_publish(pool.clone());
+            }
+            let cache = Arc::new(cache);
+            if config.read_cache_notify {
+                cache.listen(config.db_url.clone());
+            }
+            repo = repo.with_cache(cache);
+        }
+
+        // Optionally coalesce concurrent inserts into multi-row inserts
+        if config.write_batch_size > 0 {
+            let window = Duration::from_millis(config.write_batch_window_ms);
+            let batcher = WriteBatcher::start(pool, config.write_batch_size, window);
+            repo = repo.with_batcher(batcher);
+        }
+
+        // Optionally route reads to a replica
+        if let Some(pool) = PgPoolBuilder::build_replica(&config).await? {
+            let replica = Arc::new(Replica::new(pool, config.db_replica_max_lag_ms));
+            replica.monitor(Duration::from_secs(config.db_replica_check_secs));
+            repo = repo.with_replica(replica);
+        }
+
+        // Optionally accept JWT bearer tokens, refreshing signing keys in the background
+        let jwks = Jwks::from_config(&config).await?;
+        if let Some(jwks) = jwks.as_ref() {
+            jwks.watch(Duration::from_secs(config.jwt_jwks_refresh_secs));
+        }
+
+        // Lock out password guessing per account and per client address
+        let login_throttle = Arc::new(LoginThrottle::new(
+            config.login_max_failures_per_account,
+            config.login_max_failures_per_ip,
+            Duration::from_secs(config.login_lockout_secs),
+        ));
+
+        Ok(Self {
+            config,
+            repo: Arc::new(repo),
+            jwks,
+            login_throttle,
+        })
     }
 }
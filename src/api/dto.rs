@@ -1,14 +1,29 @@
 use crate::{
-    domain::{Status, Task},
+    domain::{Role, Status, Story, Task},
     Error, Result,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::str::FromStr;
 
 /// Limit name size in http request body.
 const MAX_NAME_LEN: usize = 100;
 
+/// Limit the number of items in a batch request body.
+const MAX_BATCH_LEN: usize = 1000;
+
+/// Username length limits.
+const MIN_USERNAME_LEN: usize = 3;
+const MAX_USERNAME_LEN: usize = 32;
+
+/// Password length limits; the upper bound keeps hashing cost bounded.
+const MIN_PASSWORD_LEN: usize = 8;
+const MAX_PASSWORD_LEN: usize = 128;
+
+/// Limit member subject size in http request body.
+const MAX_MEMBER_LEN: usize = 255;
+
 /// The request body for creating or updating stories
 #[derive(Debug, Deserialize)]
 pub struct StoryBody {
@@ -58,6 +73,287 @@ impl CreateTaskBody {
     }
 }
 
+/// A single task in a batch POST body; the story comes from the path.
+#[derive(Debug, Deserialize)]
+pub struct BatchTaskBody {
+    pub name: String,
+}
+
+impl BatchTaskBody {
+    /// Sanitize and validate all task names in a batch, collecting errors per item.
+    pub fn validate_all(bodies: &[BatchTaskBody]) -> Result<Vec<String>> {
+        if bodies.is_empty() || bodies.len() > MAX_BATCH_LEN {
+            return Err(Error::invalid_args("tasks: invalid batch length"));
+        }
+
+        let mut messages = Vec::new();
+        let mut names = Vec::with_capacity(bodies.len());
+        for (i, body) in bodies.iter().enumerate() {
+            let name = body.name.trim();
+            if name.is_empty() || name.len() > MAX_NAME_LEN {
+                messages.push(format!("[{}].name: invalid length", i));
+            } else {
+                names.push(name.to_string());
+            }
+        }
+
+        if messages.is_empty() {
+            Ok(names)
+        } else {
+            Err(Error::InvalidArgs { messages })
+        }
+    }
+}
+
+/// The PATCH body for setting the status of many tasks by id
+#[derive(Debug, Deserialize)]
+pub struct TaskStatusesBody {
+    pub ids: Vec<i32>,
+    pub status: String,
+}
+
+impl TaskStatusesBody {
+    /// Validate task ids and status from request body
+    pub fn validate(&self) -> Result<(Vec<i32>, Status)> {
+        let mut messages = Vec::new();
+        if self.ids.is_empty() || self.ids.len() > MAX_BATCH_LEN {
+            messages.push("ids: invalid length".into());
+        }
+        if self.ids.iter().any(|id| *id <= 0) {
+            messages.push("ids: must be > 0".into());
+        }
+        let status = Status::from_str(&self.status);
+        if status.is_err() {
+            messages.push("status: invalid enum variant".into());
+        }
+        match status {
+            Ok(status) if messages.is_empty() => Ok((self.ids.clone(), status)),
+            _ => Err(Error::InvalidArgs { messages }),
+        }
+    }
+}
+
+/// The PATCH body for setting the status of all tasks in a story
+#[derive(Debug, Deserialize)]
+pub struct StatusBody {
+    pub status: String,
+}
+
+impl StatusBody {
+    /// Validate status from request body
+    pub fn validate(&self) -> Result<Status> {
+        Status::from_str(&self.status)
+            .map_err(|_| Error::invalid_args("status: invalid enum variant"))
+    }
+}
+
+/// The query parameters for filtering the tasks of a story by status
+#[derive(Debug, Deserialize, Default)]
+pub struct StatusFilter {
+    pub status: Option<String>,
+}
+
+impl StatusFilter {
+    /// Validate the optional status filter
+    pub fn validate(&self) -> Result<Option<Status>> {
+        self.status
+            .as_deref()
+            .map(Status::from_str)
+            .transpose()
+            .map_err(|_| Error::invalid_args("status: invalid enum variant"))
+    }
+}
+
+/// The response body for bulk operations
+#[derive(Debug, Serialize)]
+pub struct CountDto {
+    pub count: u64,
+}
+
+/// An id in a batch operation: a literal id, or `"$name"` for an id created earlier in the batch.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum IdRef {
+    Id(i32),
+    Ref(String),
+}
+
+/// A single operation in a transactional batch POST body.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOpBody {
+    CreateStory {
+        #[serde(rename = "ref")]
+        reference: Option<String>,
+        name: String,
+    },
+    CreateTask {
+        #[serde(rename = "ref")]
+        reference: Option<String>,
+        story_id: IdRef,
+        name: String,
+    },
+    UpdateStory {
+        id: IdRef,
+        name: String,
+    },
+    UpdateTask {
+        id: IdRef,
+        name: Option<String>,
+        status: Option<String>,
+    },
+    DeleteStory {
+        id: IdRef,
+    },
+    DeleteTask {
+        id: IdRef,
+    },
+}
+
+/// A validated batch operation; refs are known to be created by an earlier operation.
+#[derive(Debug)]
+pub enum BatchOp {
+    CreateStory {
+        reference: Option<String>,
+        name: String,
+    },
+    CreateTask {
+        reference: Option<String>,
+        story_id: IdRef,
+        name: String,
+    },
+    UpdateStory {
+        id: IdRef,
+        name: String,
+    },
+    UpdateTask {
+        id: IdRef,
+        name: Option<String>,
+        status: Option<Status>,
+    },
+    DeleteStory {
+        id: IdRef,
+    },
+    DeleteTask {
+        id: IdRef,
+    },
+}
+
+impl BatchOpBody {
+    /// Sanitize and validate all operations in a batch, collecting errors per item.
+    pub fn validate_all(bodies: Vec<BatchOpBody>) -> Result<Vec<BatchOp>> {
+        if bodies.is_empty() || bodies.len() > MAX_BATCH_LEN {
+            return Err(Error::invalid_args("ops: invalid batch length"));
+        }
+
+        let mut messages = Vec::new();
+        let mut refs = HashSet::new();
+        let mut ops = Vec::with_capacity(bodies.len());
+
+        // Validation helpers that record errors against the operation index
+        let check_name = |i: usize, name: &str, messages: &mut Vec<String>| {
+            let name = name.trim();
+            if name.is_empty() || name.len() > MAX_NAME_LEN {
+                messages.push(format!("[{}].name: invalid length", i));
+            }
+            name.to_string()
+        };
+        let check_id = |i: usize,
+                        field: &str,
+                        id: &IdRef,
+                        refs: &HashSet<String>,
+                        messages: &mut Vec<String>| {
+            match id {
+                IdRef::Id(id) if *id <= 0 => {
+                    messages.push(format!("[{}].{}: must be > 0", i, field))
+                }
+                IdRef::Ref(r) if !r.strip_prefix('$').is_some_and(|r| refs.contains(r)) => {
+                    messages.push(format!("[{}].{}: unknown ref {}", i, field, r))
+                }
+                _ => {}
+            }
+        };
+        let check_ref = |i: usize,
+                         reference: &Option<String>,
+                         refs: &mut HashSet<String>,
+                         messages: &mut Vec<String>| {
+            if let Some(r) = reference {
+                if r.is_empty() || !refs.insert(r.clone()) {
+                    messages.push(format!("[{}].ref: invalid or duplicate", i));
+                }
+            }
+        };
+
+        for (i, body) in bodies.into_iter().enumerate() {
+            let op = match body {
+                BatchOpBody::CreateStory { reference, name } => {
+                    let name = check_name(i, &name, &mut messages);
+                    check_ref(i, &reference, &mut refs, &mut messages);
+                    BatchOp::CreateStory { reference, name }
+                }
+                BatchOpBody::CreateTask {
+                    reference,
+                    story_id,
+                    name,
+                } => {
+                    let name = check_name(i, &name, &mut messages);
+                    check_id(i, "story_id", &story_id, &refs, &mut messages);
+                    check_ref(i, &reference, &mut refs, &mut messages);
+                    BatchOp::CreateTask {
+                        reference,
+                        story_id,
+                        name,
+                    }
+                }
+                BatchOpBody::UpdateStory { id, name } => {
+                    let name = check_name(i, &name, &mut messages);
+                    check_id(i, "id", &id, &refs, &mut messages);
+                    BatchOp::UpdateStory { id, name }
+                }
+                BatchOpBody::UpdateTask { id, name, status } => {
+                    check_id(i, "id", &id, &refs, &mut messages);
+                    if name.is_none() && status.is_none() {
+                        messages.push(format!("[{}]: name and/or status must be provided", i));
+                    }
+                    let name = name.map(|n| check_name(i, &n, &mut messages));
+                    let status = status.and_then(|s| match Status::from_str(&s) {
+                        Ok(status) => Some(status),
+                        Err(_) => {
+                            messages.push(format!("[{}].status: invalid enum variant", i));
+                            None
+                        }
+                    });
+                    BatchOp::UpdateTask { id, name, status }
+                }
+                BatchOpBody::DeleteStory { id } => {
+                    check_id(i, "id", &id, &refs, &mut messages);
+                    BatchOp::DeleteStory { id }
+                }
+                BatchOpBody::DeleteTask { id } => {
+                    check_id(i, "id", &id, &refs, &mut messages);
+                    BatchOp::DeleteTask { id }
+                }
+            };
+            ops.push(op);
+        }
+
+        if messages.is_empty() {
+            Ok(ops)
+        } else {
+            Err(Error::InvalidArgs { messages })
+        }
+    }
+}
+
+/// The result of a single batch operation
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchResultDto {
+    Story(Story),
+    Task(Task),
+    Deleted(u64),
+}
+
 /// The PATCH body for updating tasks
 #[derive(Debug, Deserialize)]
 pub struct PatchTaskBody {
@@ -103,3 +399,97 @@ impl PatchTaskBody {
         }
     }
 }
+
+/// The request body for registering and logging in
+#[derive(Deserialize)]
+pub struct CredentialsBody {
+    username: String,
+    password: String,
+}
+
+/// Never log passwords
+impl Debug for CredentialsBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CredentialsBody")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CredentialsBody {
+    /// Validate credentials, normalizing the username to lowercase.
+    pub fn validate(self) -> Result<(String, String)> {
+        let mut messages = Vec::new();
+
+        let username = self.username.trim().to_lowercase();
+        let valid_chars = username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if username.len() < MIN_USERNAME_LEN || username.len() > MAX_USERNAME_LEN {
+            messages.push("username: invalid length".into());
+        } else if !valid_chars {
+            messages.push("username: only letters, digits, '_', '-' and '.' allowed".into());
+        }
+        let len = self.password.chars().count();
+        if !(MIN_PASSWORD_LEN..=MAX_PASSWORD_LEN).contains(&len) {
+            messages.push("password: invalid length".into());
+        }
+
+        if messages.is_empty() {
+            Ok((username, self.password))
+        } else {
+            Err(Error::InvalidArgs { messages })
+        }
+    }
+}
+
+/// The request body for sharing a story with a member
+#[derive(Debug, Deserialize)]
+pub struct MemberBody {
+    member: String,
+    role: String,
+}
+
+impl MemberBody {
+    /// Validate the member's subject and role from request body
+    pub fn validate(&self) -> Result<(String, Role)> {
+        let mut messages = Vec::new();
+
+        let member = self.member.trim();
+        if member.is_empty() || member.len() > MAX_MEMBER_LEN {
+            messages.push("member: invalid length".into());
+        }
+        let role = validate_role(&self.role);
+        if let Err(message) = &role {
+            messages.push(message.clone());
+        }
+
+        match role {
+            Ok(role) if messages.is_empty() => Ok((member.to_string(), role)),
+            _ => Err(Error::InvalidArgs { messages }),
+        }
+    }
+}
+
+/// The request body for changing a member's role
+#[derive(Debug, Deserialize)]
+pub struct RoleBody {
+    role: String,
+}
+
+impl RoleBody {
+    /// Validate role from request body
+    pub fn validate(&self) -> Result<Role> {
+        validate_role(&self.role).map_err(|message| Error::InvalidArgs {
+            messages: vec![message],
+        })
+    }
+}
+
+/// Members are viewers or editors; stories have a single owner.
+fn validate_role(role: &str) -> std::result::Result<Role, String> {
+    match Role::from_str(role) {
+        Ok(role @ (Role::Viewer | Role::Editor)) => Ok(role),
+        _ => Err("role: must be viewer or editor".into()),
+    }
+}
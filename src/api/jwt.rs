@@ -0,0 +1,401 @@
+use crate::{
+    config::Config,
+    domain::{Principal, Scope},
+    Error, Result,
+};
+use jsonwebtoken::{
+    decode, decode_header,
+    errors::ErrorKind,
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde::Deserialize;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Minimum time between refreshes triggered by tokens signed with an unknown key,
+/// so a flood of bad tokens can't hammer the JWKS endpoint.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Time allowed to fetch a JWKS over http.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the signing keys are published.
+enum Source {
+    File(String),
+    Url(String),
+}
+
+/// A key from the JWKS, with the algorithm tokens signed by it must use.
+struct VerifyingKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+/// Claims read from a verified token.
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    #[serde(default)]
+    scope: Option<Scopes>,
+    #[serde(default)]
+    scp: Option<Scopes>,
+}
+
+/// Scopes granted by a token: a space separated string or a list, depending on the issuer.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Scopes {
+    Joined(String),
+    List(Vec<String>),
+}
+
+impl Scopes {
+    fn iter(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        match self {
+            Self::Joined(s) => Box::new(s.split_whitespace()),
+            Self::List(list) => Box::new(list.iter().map(String::as_str)),
+        }
+    }
+}
+
+/// Verifies JWT bearer tokens against a JWKS, checking issuer, audience and expiry.
+/// Keys are refreshed periodically, and early when a token names a key that isn't known
+/// yet, so signing key rotation needs no restart.
+pub struct Jwks {
+    source: Source,
+    validation: Validation,
+    client: reqwest::Client,
+    keys: RwLock<Vec<Arc<VerifyingKey>>>,
+    last_refresh: Mutex<Instant>,
+}
+
+impl Jwks {
+    /// Load keys from the file or url in config; `None` when JWTs aren't configured.
+    pub async fn from_config(config: &Config) -> Result<Option<Arc<Self>>> {
+        let source = match (&config.jwt_jwks_path, &config.jwt_jwks_url) {
+            (Some(path), None) => Source::File(path.clone()),
+            (None, Some(url)) => Source::Url(url.clone()),
+            (None, None) => return Ok(None),
+            _ => {
+                let message = "JWT_JWKS_PATH and JWT_JWKS_URL can't both be set";
+                return Err(Error::internal(message.into()));
+            }
+        };
+
+        let validation = validation(
+            &config.jwt_issuer,
+            &config.jwt_audience,
+            config.jwt_leeway_secs,
+        );
+        Self::load(source, validation)
+            .await
+            .map(|jwks| Some(Arc::new(jwks)))
+    }
+
+    /// Load keys from a source, verifying tokens with the given validation.
+    async fn load(source: Source, validation: Validation) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .map_err(|err| Error::internal(err.to_string()))?;
+
+        let jwks = Self {
+            source,
+            validation,
+            client,
+            keys: RwLock::new(Vec::new()),
+            last_refresh: Mutex::new(Instant::now()),
+        };
+        let keys = jwks.fetch().await?;
+        *jwks.keys.write().unwrap() = keys;
+        Ok(jwks)
+    }
+
+    /// Verify a token, returning the caller it was issued to.
+    pub async fn verify(&self, token: &str) -> Result<Principal> {
+        let header = decode_header(token).map_err(|_| Error::unauthorized("invalid token"))?;
+        let key = match self.find(header.kid.as_deref()) {
+            Some(key) => key,
+            None => {
+                self.refresh_unknown_kid().await;
+                self.find(header.kid.as_deref())
+                    .ok_or_else(|| Error::unauthorized("unknown signing key"))?
+            }
+        };
+
+        // The key decides the algorithm, never the token
+        if header.alg != key.algorithm {
+            return Err(Error::unauthorized("invalid token"));
+        }
+        let mut validation = self.validation.clone();
+        validation.algorithms = vec![key.algorithm];
+
+        let claims = decode::<Claims>(token, &key.key, &validation)
+            .map_err(|err| match err.kind() {
+                ErrorKind::ExpiredSignature => Error::unauthorized("token expired"),
+                _ => {
+                    tracing::debug!("rejected token: {}", err);
+                    Error::unauthorized("invalid token")
+                }
+            })?
+            .claims;
+
+        // Scopes come in `scope` or `scp`; the broadest known scope applies
+        let scope = [&claims.scope, &claims.scp]
+            .into_iter()
+            .flatten()
+            .flat_map(Scopes::iter)
+            .filter_map(|s| Scope::from_str(s).ok())
+            .max()
+            .ok_or_else(|| Error::forbidden("token grants no scope".into()))?;
+
+        // Namespaced, so an issuer's subjects can't collide with local users or api keys
+        Ok(Principal {
+            subject: format!("jwt:{}", claims.sub),
+            scope,
+        })
+    }
+
+    /// Reload keys now, keeping the current ones if the JWKS can't be read.
+    async fn refresh(&self) {
+        *self.last_refresh.lock().await = Instant::now();
+        self.reload().await;
+    }
+
+    /// Refresh keys every `interval`, if non-zero.
+    pub fn watch(self: &Arc<Self>, interval: Duration) {
+        if interval.is_zero() {
+            return;
+        }
+        let jwks = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                jwks.refresh().await;
+            }
+        });
+    }
+
+    /// Refresh for a token signed with a key not seen yet, unless refreshed recently.
+    /// Concurrent callers wait for one refresh instead of each fetching.
+    async fn refresh_unknown_kid(&self) {
+        let mut last_refresh = self.last_refresh.lock().await;
+        if last_refresh.elapsed() < MIN_REFRESH_INTERVAL {
+            return;
+        }
+        *last_refresh = Instant::now();
+        self.reload().await;
+    }
+
+    async fn reload(&self) {
+        match self.fetch().await {
+            Ok(keys) => {
+                tracing::info!("loaded {} JWKS keys", keys.len());
+                *self.keys.write().unwrap() = keys;
+            }
+            Err(err) => tracing::error!("failed to refresh JWKS: {}", err),
+        }
+    }
+
+    /// The key for a kid; tokens without one may only be used with a single key JWKS.
+    fn find(&self, kid: Option<&str>) -> Option<Arc<VerifyingKey>> {
+        let keys = self.keys.read().unwrap();
+        let key = match kid {
+            Some(kid) => keys.iter().find(|k| k.kid.as_deref() == Some(kid))?,
+            None if keys.len() == 1 => &keys[0],
+            None => return None,
+        };
+        Some(Arc::clone(key))
+    }
+
+    /// Read and parse the JWKS, skipping keys that can't be used.
+    async fn fetch(&self) -> Result<Vec<Arc<VerifyingKey>>> {
+        let (name, body) = match &self.source {
+            Source::File(path) => {
+                let body = tokio::fs::read_to_string(path)
+                    .await
+                    .map_err(|err| Error::internal(format!("{}: {}", path, err)))?;
+                (path, body)
+            }
+            Source::Url(url) => {
+                let body = self
+                    .client
+                    .get(url)
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|err| Error::internal(format!("{}: {}", url, err)))?
+                    .text()
+                    .await
+                    .map_err(|err| Error::internal(format!("{}: {}", url, err)))?;
+                (url, body)
+            }
+        };
+        let set: JwkSet = serde_json::from_str(&body)
+            .map_err(|err| Error::internal(format!("{}: {}", name, err)))?;
+
+        let keys: Vec<Arc<VerifyingKey>> = set
+            .keys
+            .iter()
+            .filter_map(|jwk| match verifying_key(jwk) {
+                Ok(key) => Some(Arc::new(key)),
+                Err(err) => {
+                    tracing::warn!("{}: skipping key {:?}: {}", name, jwk.common.key_id, err);
+                    None
+                }
+            })
+            .collect();
+        if keys.is_empty() {
+            return Err(Error::internal(format!("{}: no usable keys", name)));
+        }
+        Ok(keys)
+    }
+}
+
+/// Require a signed, unexpired token for the issuer and audience.
+fn validation(issuer: &str, audience: &str, leeway_secs: u64) -> Validation {
+    let mut validation = Validation::default();
+    validation.leeway = leeway_secs;
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[audience]);
+    validation
+}
+
+/// Build a verifying key, inferring the algorithm from the key type when `alg` is absent.
+fn verifying_key(jwk: &Jwk) -> Result<VerifyingKey, String> {
+    let algorithm = match (jwk.common.key_algorithm, &jwk.algorithm) {
+        (Some(alg), _) => Algorithm::from_str(&alg.to_string()).map_err(|e| e.to_string())?,
+        (None, AlgorithmParameters::RSA(_)) => Algorithm::RS256,
+        (None, AlgorithmParameters::EllipticCurve(ec)) => match ec.curve {
+            EllipticCurve::P256 => Algorithm::ES256,
+            EllipticCurve::P384 => Algorithm::ES384,
+            _ => return Err("unsupported curve".into()),
+        },
+        (None, AlgorithmParameters::OctetKeyPair(_)) => Algorithm::EdDSA,
+        (None, AlgorithmParameters::OctetKey(_)) => Algorithm::HS256,
+    };
+    let key = DecodingKey::from_jwk(jwk).map_err(|e| e.to_string())?;
+    Ok(VerifyingKey {
+        kid: jwk.common.key_id.clone(),
+        algorithm,
+        key,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::{json, Value};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const SECRET: &[u8] = b"local-test-secret-for-jwt-verification";
+    const ISSUER: &str = "https://issuer.test";
+    const AUDIENCE: &str = "bb8-todos";
+
+    /// Verifier for a local JWKS file holding one HS256 key, `k1`.
+    async fn jwks(name: &str) -> Jwks {
+        let set = json!({"keys": [{
+            "kty": "oct",
+            "kid": "k1",
+            "alg": "HS256",
+            "k": URL_SAFE_NO_PAD.encode(SECRET),
+        }]});
+        let path =
+            std::env::temp_dir().join(format!("bb8-todos-{}-{}.json", name, std::process::id()));
+        std::fs::write(&path, set.to_string()).unwrap();
+        let source = Source::File(path.to_string_lossy().into_owned());
+        let jwks = Jwks::load(source, validation(ISSUER, AUDIENCE, 0))
+            .await
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+        jwks
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn claims() -> Value {
+        json!({
+            "sub": "7",
+            "iss": ISSUER,
+            "aud": AUDIENCE,
+            "exp": now() + 60,
+            "scope": "read write",
+        })
+    }
+
+    fn token(alg: Algorithm, kid: Option<&str>, claims: &Value) -> String {
+        let mut header = Header::new(alg);
+        header.kid = kid.map(String::from);
+        encode(&header, claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    async fn rejection(claims: Value) -> String {
+        let token = token(Algorithm::HS256, Some("k1"), &claims);
+        match jwks("rejection").await.verify(&token).await {
+            Err(Error::Unauthorized { message }) => message.to_string(),
+            other => panic!("expected unauthorized, got {:?}", other.map(|p| p.subject)),
+        }
+    }
+
+    #[tokio::test]
+    async fn accepts_a_valid_token_with_a_namespaced_subject() {
+        let token = token(Algorithm::HS256, Some("k1"), &claims());
+        let principal = jwks("valid").await.verify(&token).await.unwrap();
+        assert_eq!(principal.subject, "jwt:7");
+        assert_eq!(principal.scope, Scope::Write);
+    }
+
+    #[tokio::test]
+    async fn rejects_another_issuer() {
+        let mut claims = claims();
+        claims["iss"] = json!("https://other.test");
+        assert_eq!(rejection(claims).await, "invalid token");
+    }
+
+    #[tokio::test]
+    async fn rejects_another_audience() {
+        let mut claims = claims();
+        claims["aud"] = json!("other");
+        assert_eq!(rejection(claims).await, "invalid token");
+    }
+
+    #[tokio::test]
+    async fn rejects_an_expired_token() {
+        let mut claims = claims();
+        claims["exp"] = json!(now() - 60);
+        assert_eq!(rejection(claims).await, "token expired");
+    }
+
+    #[tokio::test]
+    async fn rejects_an_unknown_kid() {
+        let token = token(Algorithm::HS256, Some("k2"), &claims());
+        let result = jwks("unknown-kid").await.verify(&token).await;
+        assert!(
+            matches!(result, Err(Error::Unauthorized { message }) if message == "unknown signing key")
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_an_algorithm_the_key_is_not_for() {
+        // Signed with the right secret, but not with the key's algorithm
+        let token = token(Algorithm::HS384, Some("k1"), &claims());
+        let result = jwks("wrong-alg").await.verify(&token).await;
+        assert!(
+            matches!(result, Err(Error::Unauthorized { message }) if message == "invalid token")
+        );
+    }
+}
@@ -0,0 +1,29 @@
+use crate::Error;
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use http_body_util::Limited;
+
+/// Cap a decompressed request body, so every reader stops at the limit
+/// instead of inflating a small compressed body without bound.
+pub fn limit_body(req: Request, max_bytes: usize) -> Request {
+    req.map(|body| Body::new(Limited::new(body, max_bytes)))
+}
+
+/// Replace the plain text 413 sent when a request body exceeds the limit with an error body.
+pub async fn too_large(response: Response) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE || is_json {
+        return response;
+    }
+    Error::TooLarge {
+        message: "request body exceeds the size limit".into(),
+    }
+    .into_response()
+}
@@ -0,0 +1,104 @@
+use crate::{
+    api::{
+        auth::{Auth, ReadScope, WriteScope},
+        dto::{MemberBody, RoleBody},
+        Ctx,
+    },
+    domain::Role,
+    Error, Result,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, patch},
+    Json, Router,
+};
+use std::sync::Arc;
+
+/// API routes for the members a story is shared with
+pub fn routes() -> Router<Arc<Ctx>> {
+    Router::new()
+        .route("/stories/:id/members", get(get_members).post(add_member))
+        .route(
+            "/stories/:id/members/:member",
+            patch(update_member).delete(remove_member),
+        )
+}
+
+/// List the members of a story
+async fn get_members(
+    auth: Auth<ReadScope>,
+    Path(id): Path<i32>,
+    State(ctx): State<Arc<Ctx>>,
+) -> Result<impl IntoResponse> {
+    tracing::info!("GET /stories/{}/members", id);
+    ctx.repo
+        .check_story_access(id, &auth.principal, Role::Viewer)
+        .await?;
+    let members = ctx.repo.select_members(id).await?;
+    Ok(Json(members))
+}
+
+/// Share a story with a member
+async fn add_member(
+    auth: Auth<WriteScope>,
+    Path(id): Path<i32>,
+    State(ctx): State<Arc<Ctx>>,
+    Json(body): Json<MemberBody>,
+) -> Result<impl IntoResponse> {
+    tracing::info!("POST /stories/{}/members", id);
+    tracing::debug!("body = {:?}", body);
+    let (member, role) = body.validate()?;
+    let story = ctx.repo.select_story(id).await?;
+    let caller_role = ctx.repo.story_role(&story, &auth.principal).await?;
+    Role::require(caller_role, Role::Owner, id)?;
+    if story.owner.as_deref() == Some(member.as_str()) {
+        return Err(Error::invalid_args("member: already the owner"));
+    }
+    let member = ctx.repo.insert_member(id, member, role).await?;
+    Ok((StatusCode::CREATED, Json(member)))
+}
+
+/// Change a member's role
+async fn update_member(
+    auth: Auth<WriteScope>,
+    Path((id, member)): Path<(i32, String)>,
+    State(ctx): State<Arc<Ctx>>,
+    Json(body): Json<RoleBody>,
+) -> Result<impl IntoResponse> {
+    tracing::info!("PATCH /stories/{}/members/{}", id, member);
+    tracing::debug!("body = {:?}", body);
+    let role = body.validate()?;
+    ctx.repo
+        .check_story_access(id, &auth.principal, Role::Owner)
+        .await?;
+    let member = ctx.repo.update_member(id, member, role).await?;
+    Ok(Json(member))
+}
+
+/// Stop sharing a story with a member; members may remove themselves
+async fn remove_member(
+    auth: Auth<WriteScope>,
+    Path((id, member)): Path<(i32, String)>,
+    State(ctx): State<Arc<Ctx>>,
+) -> StatusCode {
+    tracing::info!("DELETE /stories/{}/members/{}", id, member);
+    let needed = if member == auth.principal.subject {
+        Role::Viewer
+    } else {
+        Role::Owner
+    };
+    if let Err(err) = ctx
+        .repo
+        .check_story_access(id, &auth.principal, needed)
+        .await
+    {
+        return err.into();
+    }
+    match ctx.repo.delete_member(id, &member).await {
+        Err(err) => err.into(),
+        Ok(0) => StatusCode::NOT_FOUND,
+        Ok(_) => StatusCode::NO_CONTENT,
+    }
+}
@@ -0,0 +1,24 @@
+use super::auth::{AdminScope, Auth};
+use super::Ctx;
+use crate::repo::SingleFlightStats;
+use axum::{extract::State, response::IntoResponse, routing::get, Json, Router};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// API route for runtime metrics
+pub fn routes() -> Router<Arc<Ctx>> {
+    Router::new().route("/metrics", get(get_metrics))
+}
+
+/// The response body for runtime metrics
+#[derive(Debug, Serialize)]
+struct MetricsDto {
+    select_story: SingleFlightStats,
+}
+
+/// Get runtime metrics
+async fn get_metrics(_: Auth<AdminScope>, State(ctx): State<Arc<Ctx>>) -> impl IntoResponse {
+    Json(MetricsDto {
+        select_story: ctx.repo.story_read_stats(),
+    })
+}
@@ -1,14 +1,31 @@
-use axum::Router;
+use axum::{extract::DefaultBodyLimit, middleware, Router};
 use std::sync::Arc;
+use tower_http::compression::{predicate::SizeAbove, CompressionLayer};
+use tower_http::decompression::RequestDecompressionLayer;
 
+mod account;
+mod auth;
+mod batch;
+mod conditional;
+mod cors;
 mod ctx;
 mod dto;
+mod jwt;
+mod limit;
+mod member;
+mod metrics;
 mod page;
+mod password;
+pub mod record;
 mod status;
+mod sticky;
 mod story;
 mod task;
+mod throttle;
 
 pub use ctx::Ctx;
+pub use jwt::Jwks;
+pub use throttle::LoginThrottle;
 
 /// The http/json presentation layer
 pub struct Api {
@@ -22,10 +39,59 @@ impl Api {
     }
 
     /// Combine module routes into a top-level api router.
-    pub fn routes(self) -> Router {
-        status::routes()
+    pub async fn routes(self) -> Router {
+        let mut routes = status::routes()
+            .merge(account::routes())
+            .merge(batch::routes())
+            .merge(member::routes())
+            .merge(metrics::routes())
             .merge(story::routes())
-            .merge(task::routes())
-            .with_state(self.ctx)
+            .merge(task::routes());
+
+        // ETags and Cache-Control for story and task reads
+        let layer =
+            middleware::from_fn_with_state(Arc::clone(&self.ctx), conditional::conditional_get);
+        routes = routes.layer(layer);
+
+        // Read-your-writes stickiness only matters when reads can go to a replica
+        if self.ctx.repo.has_replica() {
+            let layer =
+                middleware::from_fn_with_state(Arc::clone(&self.ctx), sticky::read_your_writes);
+            routes = routes.layer(layer);
+        }
+
+        // Optionally record all traffic for later replay
+        if let Some(path) = self.ctx.config.record_path.as_ref() {
+            let headers = self.ctx.config.record_headers.clone();
+            let max_body_bytes = self.ctx.config.max_request_body_bytes;
+            let recorder = record::Recorder::open(path, headers, max_body_bytes)
+                .await
+                .expect("failed to open recording file");
+            let layer = middleware::from_fn_with_state(Arc::new(recorder), record::record);
+            routes = routes.layer(layer);
+        }
+
+        // Limit request bodies after decompression, for extractors and for any middleware
+        // that reads the body, and report the limit as an error body
+        let max_body_bytes = self.ctx.config.max_request_body_bytes;
+        routes = routes
+            .layer(DefaultBodyLimit::max(max_body_bytes))
+            .layer(middleware::map_request(move |req| async move {
+                limit::limit_body(req, max_body_bytes)
+            }))
+            .layer(middleware::map_response(limit::too_large));
+
+        // Negotiate compressed request and response bodies
+        let compress_when = SizeAbove::new(self.ctx.config.compression_min_bytes);
+        routes = routes
+            .layer(RequestDecompressionLayer::new())
+            .layer(CompressionLayer::new().compress_when(compress_when));
+
+        // Outermost, so preflights are answered and every response gets CORS headers
+        if let Some(layer) = cors::layer(&self.ctx.config) {
+            routes = routes.layer(layer);
+        }
+
+        routes.with_state(self.ctx)
     }
 }
@@ -0,0 +1,51 @@
+use crate::{Error, Result};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use std::sync::OnceLock;
+
+/// Hash a password with argon2id and a random salt, off the async runtime.
+pub async fn hash(password: String) -> Result<String> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|err| Error::internal(err.to_string()))
+    })
+    .await
+    .map_err(|err| Error::internal(err.to_string()))?
+}
+
+/// Check a password against a stored hash, off the async runtime.
+/// Without a stored hash, e.g. for an unknown username, a dummy hash is checked instead,
+/// so the response time doesn't reveal which usernames exist.
+pub async fn verify(password: String, stored: Option<String>) -> Result<bool> {
+    tokio::task::spawn_blocking(move || match stored {
+        Some(stored) => matches(&password, &stored),
+        None => matches(&password, dummy_hash()).map(|_| false),
+    })
+    .await
+    .map_err(|err| Error::internal(err.to_string()))?
+}
+
+fn matches(password: &str, stored: &str) -> Result<bool> {
+    let hash = PasswordHash::new(stored).map_err(|err| Error::internal(err.to_string()))?;
+    Ok(Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok())
+}
+
+/// A hash of a random password, made once and never matched.
+fn dummy_hash() -> &'static str {
+    static DUMMY: OnceLock<String> = OnceLock::new();
+    DUMMY.get_or_init(|| {
+        let salt = SaltString::generate(&mut OsRng);
+        let password = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_str().as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .unwrap_or_default()
+    })
+}
@@ -0,0 +1,209 @@
+use crate::{Error, Result};
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use http_body_util::LengthLimitError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncWriteExt, BufWriter},
+    sync::mpsc,
+};
+
+/// Recordings waiting to be written; requests are never blocked on the file.
+const QUEUE_SIZE: usize = 10_000;
+
+/// Routes never recorded, since their bodies carry passwords and session tokens.
+const UNRECORDED_PREFIX: &str = "/auth/";
+
+/// A recorded request body or response body.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedBody {
+    #[default]
+    Empty,
+    Text(String),
+    Base64(String),
+}
+
+impl RecordedBody {
+    /// Record body bytes as text when possible.
+    pub fn new(bytes: &[u8]) -> Self {
+        if bytes.is_empty() {
+            Self::Empty
+        } else if let Ok(text) = std::str::from_utf8(bytes) {
+            Self::Text(text.to_string())
+        } else {
+            Self::Base64(STANDARD.encode(bytes))
+        }
+    }
+
+    /// The raw body bytes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        match self {
+            Self::Empty => Ok(Vec::new()),
+            Self::Text(text) => Ok(text.as_bytes().to_vec()),
+            Self::Base64(b64) => Ok(STANDARD.decode(b64)?),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        matches!(self, Self::Empty)
+    }
+}
+
+/// A single recorded request and the response it received; one JSON line in a recording.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Recording {
+    pub ts_ms: u64,
+    pub method: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "RecordedBody::is_empty")]
+    pub body: RecordedBody,
+    pub duration_us: u64,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "RecordedBody::is_empty")]
+    pub response_body: RecordedBody,
+}
+
+/// Appends recordings to a JSON Lines file from a background task.
+pub struct Recorder {
+    headers: Vec<String>,
+    max_body_bytes: usize,
+    sender: mpsc::Sender<Recording>,
+}
+
+impl Recorder {
+    /// Open a recording file for appending and start the writer task.
+    /// Request bodies over `max_body_bytes` are refused rather than buffered.
+    pub async fn open(
+        path: &str,
+        headers: Vec<String>,
+        max_body_bytes: usize,
+    ) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        let (sender, mut receiver) = mpsc::channel::<Recording>(QUEUE_SIZE);
+
+        tokio::spawn(async move {
+            let mut writer = BufWriter::new(file);
+            while let Some(recording) = receiver.recv().await {
+                if let Err(err) = write_line(&mut writer, &recording).await {
+                    tracing::error!("failed to write recording: {}", err);
+                }
+                // Flush once the queue is drained
+                if receiver.is_empty() {
+                    if let Err(err) = writer.flush().await {
+                        tracing::error!("failed to flush recordings: {}", err);
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            headers,
+            max_body_bytes,
+            sender,
+        })
+    }
+}
+
+/// Serialize a recording as a single line.
+async fn write_line(
+    writer: &mut BufWriter<tokio::fs::File>,
+    recording: &Recording,
+) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(recording)?;
+    line.push(b'\n');
+    writer.write_all(&line).await
+}
+
+/// Record requests and their responses, except account routes.
+pub async fn record(State(recorder): State<Arc<Recorder>>, req: Request, next: Next) -> Response {
+    if req.uri().path().starts_with(UNRECORDED_PREFIX) {
+        return next.run(req).await;
+    }
+    let ts_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    let start = Instant::now();
+
+    // Buffer the request body so it can be both recorded and handled
+    let (parts, body) = req.into_parts();
+    let body = match to_bytes(body, recorder.max_body_bytes).await {
+        Ok(body) => body,
+        Err(err) => return unreadable_request(err),
+    };
+    let mut headers = BTreeMap::new();
+    for name in &recorder.headers {
+        if let Some(value) = parts.headers.get(name).and_then(|v| v.to_str().ok()) {
+            headers.insert(name.clone(), value.to_string());
+        }
+    }
+    let mut recording = Recording {
+        ts_ms,
+        method: parts.method.to_string(),
+        path: parts.uri.path().to_string(),
+        query: parts.uri.query().map(String::from),
+        headers,
+        body: RecordedBody::new(&body),
+        duration_us: 0,
+        status: 0,
+        response_body: RecordedBody::Empty,
+    };
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    // Buffer the response body as well
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => {
+            let message = format!("failed to buffer response for recording: {}", err);
+            return Error::internal(message).into_response();
+        }
+    };
+    recording.duration_us = start.elapsed().as_micros() as u64;
+    recording.status = parts.status.as_u16();
+    recording.response_body = RecordedBody::new(&body);
+
+    if recorder.sender.try_send(recording).is_err() {
+        tracing::warn!("recording queue full, dropping request");
+    }
+
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Answer a request whose body couldn't be read, instead of handling it with a missing body.
+fn unreadable_request(err: axum::Error) -> Response {
+    // The limit may be hit here or by a reader further out, which wraps its error
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(&err);
+    let too_large = std::iter::from_fn(|| {
+        let current = source?;
+        source = current.source();
+        Some(current)
+    })
+    .any(|e| e.is::<LengthLimitError>());
+    if too_large {
+        return Error::TooLarge {
+            message: "request body exceeds the size limit".into(),
+        }
+        .into_response();
+    }
+    Error::invalid_args(&format!("failed to read request body: {}", err)).into_response()
+}
@@ -0,0 +1,74 @@
+use super::Ctx;
+use crate::repo::read_primary;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Cookie set after a successful write; holds the write time in unix millis.
+const LAST_WRITE_COOKIE: &str = "bb8_todos_last_write";
+
+/// Header alternative to the cookie, for clients that don't keep cookies.
+const LAST_WRITE_HEADER: &str = "x-last-write";
+
+/// Route reads to the primary for a short window after a client writes,
+/// so clients always see their own writes even when the replica lags.
+/// Writes read from the primary too, so their checks never see a lagging replica.
+pub async fn read_your_writes(State(ctx): State<Arc<Ctx>>, req: Request, next: Next) -> Response {
+    let window = Duration::from_secs(ctx.config.read_your_writes_secs);
+    let is_write = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    // Times in the future are ignored, so a client can't pin itself to the primary
+    let now = now_millis();
+    let is_sticky = last_write(req.headers())
+        .is_some_and(|ts| ts <= now && now - ts < window.as_millis() as u64);
+
+    let mut response = if is_write || is_sticky {
+        read_primary(next.run(req)).await
+    } else {
+        next.run(req).await
+    };
+
+    if is_write && response.status().is_success() {
+        let ts = now_millis();
+        let cookie = format!(
+            "{}={}; Max-Age={}; Path=/; HttpOnly; SameSite=Lax",
+            LAST_WRITE_COOKIE,
+            ts,
+            window.as_secs()
+        );
+        let headers = response.headers_mut();
+        headers.insert(LAST_WRITE_HEADER, HeaderValue::from(ts));
+        if let Ok(value) = HeaderValue::from_str(&cookie) {
+            headers.append(header::SET_COOKIE, value);
+        }
+    }
+
+    response
+}
+
+/// Read the last write time from the request header or cookie.
+fn last_write(headers: &HeaderMap) -> Option<u64> {
+    if let Some(value) = headers.get(LAST_WRITE_HEADER) {
+        return value.to_str().ok().and_then(|s| s.trim().parse().ok());
+    }
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == LAST_WRITE_COOKIE)
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+/// Calculate the number of milliseconds since the unix epoch.
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis() as u64
+}
@@ -1,24 +1,33 @@
 use crate::{
-    api::dto::StoryBody,
+    api::auth::{Auth, ReadScope, WriteScope},
+    api::conditional::content_etag,
+    api::dto::{BatchTaskBody, CountDto, StatusBody, StatusFilter, StoryBody},
     api::page::{Page, PageParams, PageToken},
     api::Ctx,
-    Result,
+    domain::{Principal, Role, Task},
+    Error, Result,
 };
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::get,
+    extract::{FromRequest, Path, Query, Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
     Json, Router,
 };
-use futures::TryFutureExt;
 use std::sync::Arc;
 
 /// API routes for stories
 pub fn routes() -> Router<Arc<Ctx>> {
     Router::new()
         .route("/stories", get(get_stories).post(create_story))
-        .route("/stories/:id/tasks", get(get_tasks))
+        .route("/stories/shared", get(get_shared_stories))
+        .route(
+            "/stories/:id/tasks",
+            get(get_tasks).patch(update_tasks).delete(delete_tasks),
+        )
+        // The router treats ':' as the start of a param, so custom methods like
+        // `tasks:batch` are matched with a param holding the ':batch' suffix.
+        .route("/stories/:id/tasks:method", post(tasks_method))
         .route(
             "/stories/:id",
             get(get_story).delete(delete_story).patch(update_story),
@@ -26,14 +35,19 @@ pub fn routes() -> Router<Arc<Ctx>> {
 }
 
 /// Get a story by id
-async fn get_story(Path(id): Path<i32>, State(ctx): State<Arc<Ctx>>) -> Result<impl IntoResponse> {
+async fn get_story(
+    auth: Auth<ReadScope>,
+    Path(id): Path<i32>,
+    State(ctx): State<Arc<Ctx>>,
+) -> Result<impl IntoResponse> {
     tracing::info!("GET /stories/{}", id);
-    let story = ctx.repo.select_story(id).await?;
+    let story = ctx.repo.select_story_for(id, &auth.principal).await?;
     Ok(Json(story))
 }
 
 /// Get tasks for a story
 async fn get_tasks(
+    auth: Auth<ReadScope>,
     params: Option<Query<PageParams>>,
     Path(id): Path<i32>,
     State(ctx): State<Arc<Ctx>>,
@@ -45,15 +59,88 @@ async fn get_tasks(
     let page_id = PageToken::decode(q.page_token.clone())?;
 
     // Query and create page
+    ctx.repo
+        .check_story_access(id, &auth.principal, Role::Viewer)
+        .await?;
     let data = ctx.repo.select_tasks(id, page_id).await?;
+    let etag = content_etag(&data);
     let next = data.last().and_then(|t| PageToken::encode(t.id + 1));
     let page = Page::new(None, next, data);
 
-    Ok(Json(page))
+    Ok(([(header::ETAG, etag)], Json(page)))
+}
+
+/// Set the status of all tasks in a story, optionally filtered by current status
+async fn update_tasks(
+    auth: Auth<WriteScope>,
+    filter: Option<Query<StatusFilter>>,
+    Path(id): Path<i32>,
+    State(ctx): State<Arc<Ctx>>,
+    Json(body): Json<StatusBody>,
+) -> Result<impl IntoResponse> {
+    tracing::info!("PATCH /stories/{}/tasks", id);
+    tracing::debug!("body = {:?}", body);
+    let filter = filter.unwrap_or_default().validate()?;
+    let status = body.validate()?;
+    let count = ctx
+        .repo
+        .update_story_task_statuses(id, status, filter, &auth.principal)
+        .await?;
+    Ok(Json(CountDto { count }))
+}
+
+/// Delete all tasks in a story, optionally filtered by status
+async fn delete_tasks(
+    auth: Auth<WriteScope>,
+    filter: Option<Query<StatusFilter>>,
+    Path(id): Path<i32>,
+    State(ctx): State<Arc<Ctx>>,
+) -> Result<impl IntoResponse> {
+    tracing::info!("DELETE /stories/{}/tasks", id);
+    let filter = filter.unwrap_or_default().validate()?;
+    let count = ctx
+        .repo
+        .delete_story_tasks(id, filter, &auth.principal)
+        .await?;
+    Ok(Json(CountDto { count }))
+}
+
+/// Dispatch custom methods on a story's tasks. The body is only parsed once the method is
+/// known, so an unknown method is not found whatever the body.
+async fn tasks_method(
+    auth: Auth<WriteScope>,
+    Path((id, method)): Path<(i32, String)>,
+    State(ctx): State<Arc<Ctx>>,
+    req: Request,
+) -> Response {
+    match method.as_str() {
+        ":batch" => match Json::<Vec<BatchTaskBody>>::from_request(req, &ctx).await {
+            Ok(Json(bodies)) => create_tasks(id, ctx, &auth.principal, bodies)
+                .await
+                .into_response(),
+            Err(rejection) => rejection.into_response(),
+        },
+        _ => Error::not_found(format!("unknown method: {}", method)).into_response(),
+    }
+}
+
+/// Create many tasks for a story
+async fn create_tasks(
+    id: i32,
+    ctx: Arc<Ctx>,
+    principal: &Principal,
+    bodies: Vec<BatchTaskBody>,
+) -> Result<(StatusCode, Json<Vec<Task>>)> {
+    tracing::info!("POST /stories/{}/tasks:batch", id);
+    tracing::debug!("body = {:?}", bodies);
+    let names = BatchTaskBody::validate_all(&bodies)?;
+    let tasks = ctx.repo.insert_tasks(id, names, principal).await?;
+    Ok((StatusCode::CREATED, Json(tasks)))
 }
 
-/// Get a page of stories
+/// Get a page of stories the caller owns
 async fn get_stories(
+    auth: Auth<ReadScope>,
     params: Option<Query<PageParams>>,
     State(ctx): State<Arc<Ctx>>,
 ) -> Result<impl IntoResponse> {
@@ -64,27 +151,62 @@ async fn get_stories(
     let page_id = PageToken::decode(q.page_token.clone())?;
 
     // Query and create page
-    let (prev, next, data) = ctx.repo.select_stories(page_id).await?;
+    let owner = auth.principal.owner_filter();
+    let (prev, next, data) = ctx.repo.select_stories(page_id, owner).await?;
+    let etag = content_etag(&(prev, next, &data));
     let page = Page::new(PageToken::encode(prev), PageToken::encode(next), data);
 
-    Ok(Json(page))
+    Ok(([(header::ETAG, etag)], Json(page)))
+}
+
+/// Get a page of stories shared with the caller, with their role on each
+async fn get_shared_stories(
+    auth: Auth<ReadScope>,
+    params: Option<Query<PageParams>>,
+    State(ctx): State<Arc<Ctx>>,
+) -> Result<impl IntoResponse> {
+    tracing::info!("GET /stories/shared");
+
+    // Determine page to query
+    let q = params.unwrap_or_default();
+    let page_id = PageToken::decode(q.page_token.clone())?;
+
+    // Query and create page
+    let subject = &auth.principal.subject;
+    let (prev, next, data) = ctx.repo.select_shared_stories(page_id, subject).await?;
+    let etag = content_etag(&(prev, next, &data));
+    let page = Page::new(PageToken::encode(prev), PageToken::encode(next), data);
+
+    Ok(([(header::ETAG, etag)], Json(page)))
 }
 
 /// Create a new story
 async fn create_story(
+    auth: Auth<WriteScope>,
     State(ctx): State<Arc<Ctx>>,
     Json(body): Json<StoryBody>,
 ) -> Result<impl IntoResponse> {
     tracing::info!("POST /stories");
     tracing::debug!("body = {:?}", body);
     let name = body.validate()?;
-    let story = ctx.repo.insert_story(name).await?;
+    let story = ctx.repo.insert_story(name, auth.principal.subject).await?;
     Ok((StatusCode::CREATED, Json(story)))
 }
 
 /// Delete a story by id
-async fn delete_story(Path(id): Path<i32>, State(ctx): State<Arc<Ctx>>) -> StatusCode {
+async fn delete_story(
+    auth: Auth<WriteScope>,
+    Path(id): Path<i32>,
+    State(ctx): State<Arc<Ctx>>,
+) -> StatusCode {
     tracing::info!("DELETE /stories/{}", id);
+    if let Err(err) = ctx
+        .repo
+        .check_story_access(id, &auth.principal, Role::Owner)
+        .await
+    {
+        return err.into();
+    }
     if let Ok(num_rows) = ctx.repo.delete_story(id).await {
         if num_rows > 0 {
             return StatusCode::NO_CONTENT;
@@ -95,6 +217,7 @@ async fn delete_story(Path(id): Path<i32>, State(ctx): State<Arc<Ctx>>) -> Statu
 
 /// Update an existing story
 async fn update_story(
+    auth: Auth<WriteScope>,
     Path(id): Path<i32>,
     State(ctx): State<Arc<Ctx>>,
     Json(body): Json<StoryBody>,
@@ -103,11 +226,7 @@ async fn update_story(
     tracing::debug!("body = {:?}", body);
 
     let name = body.validate()?;
-    let story = ctx
-        .repo
-        .select_story(id)
-        .and_then(|_| ctx.repo.update_story(id, name))
-        .await?;
+    let story = ctx.repo.update_story(id, name, &auth.principal).await?;
 
     Ok(Json(story))
 }
@@ -1,8 +1,10 @@
 use crate::{
     api::{
-        dto::{CreateTaskBody, PatchTaskBody},
+        auth::{Auth, ReadScope, WriteScope},
+        dto::{CountDto, CreateTaskBody, PatchTaskBody, TaskStatusesBody},
         Ctx,
     },
+    domain::Role,
     Result,
 };
 use axum::{
@@ -12,26 +14,35 @@ use axum::{
     routing::{get, post},
     Json, Router,
 };
-use futures_util::TryFutureExt;
 use std::sync::Arc;
 
 /// API routes for tasks
 pub fn routes() -> Router<Arc<Ctx>> {
-    Router::new().route("/tasks", post(create_task)).route(
-        "/tasks/:id",
-        get(get_task).delete(delete_task).patch(update_task),
-    )
+    Router::new()
+        .route("/tasks", post(create_task).patch(update_tasks))
+        .route(
+            "/tasks/:id",
+            get(get_task).delete(delete_task).patch(update_task),
+        )
 }
 
 /// Get a task by id
-async fn get_task(Path(id): Path<i32>, State(ctx): State<Arc<Ctx>>) -> Result<impl IntoResponse> {
+async fn get_task(
+    auth: Auth<ReadScope>,
+    Path(id): Path<i32>,
+    State(ctx): State<Arc<Ctx>>,
+) -> Result<impl IntoResponse> {
     tracing::info!("GET /tasks/{}", id);
-    let task = ctx.repo.select_task(id).await?;
+    let task = ctx
+        .repo
+        .select_task_for(id, &auth.principal, Role::Viewer)
+        .await?;
     Ok(Json(task))
 }
 
 /// Create a new task
 async fn create_task(
+    auth: Auth<WriteScope>,
     State(ctx): State<Arc<Ctx>>,
     Json(body): Json<CreateTaskBody>,
 ) -> Result<impl IntoResponse> {
@@ -41,16 +52,40 @@ async fn create_task(
     let (story_id, name) = body.validate()?;
     let task = ctx
         .repo
-        .select_story(story_id)
-        .and_then(|story| ctx.repo.insert_task(story.id, name))
+        .insert_task(story_id, name, &auth.principal)
         .await?;
 
     Ok((StatusCode::CREATED, Json(task)))
 }
 
+/// Set the status of many tasks by id; tasks in stories the caller can't edit are skipped
+async fn update_tasks(
+    auth: Auth<WriteScope>,
+    State(ctx): State<Arc<Ctx>>,
+    Json(body): Json<TaskStatusesBody>,
+) -> Result<impl IntoResponse> {
+    tracing::info!("PATCH /tasks");
+    tracing::debug!("body = {:?}", body);
+    let (ids, status) = body.validate()?;
+    let owner = auth.principal.owner_filter();
+    let count = ctx.repo.update_task_statuses(ids, status, owner).await?;
+    Ok(Json(CountDto { count }))
+}
+
 /// Delete a task by id
-async fn delete_task(Path(id): Path<i32>, State(ctx): State<Arc<Ctx>>) -> StatusCode {
+async fn delete_task(
+    auth: Auth<WriteScope>,
+    Path(id): Path<i32>,
+    State(ctx): State<Arc<Ctx>>,
+) -> StatusCode {
     tracing::info!("DELETE /tasks/{}", id);
+    if let Err(err) = ctx
+        .repo
+        .select_task_for(id, &auth.principal, Role::Editor)
+        .await
+    {
+        return err.into();
+    }
     match ctx.repo.delete_task(id).await {
         Err(err) => err.into(),
         Ok(num_rows) => {
@@ -65,13 +100,18 @@ async fn delete_task(Path(id): Path<i32>, State(ctx): State<Arc<Ctx>>) -> Status
 
 /// Update a task.
 async fn update_task(
+    auth: Auth<WriteScope>,
     Path(id): Path<i32>,
     State(ctx): State<Arc<Ctx>>,
     Json(body): Json<PatchTaskBody>,
 ) -> Result<impl IntoResponse> {
     tracing::info!("PATCH /tasks/{}", id);
     tracing::debug!("body = {:?}", body);
-    let existing_task = ctx.repo.select_task(id).await?;
+    // Uncached, so a stale copy can't revert a field changed elsewhere
+    let existing_task = ctx
+        .repo
+        .select_task_for_update(id, &auth.principal, Role::Editor)
+        .await?;
     let (name, status) = body.validate(existing_task)?;
     let updated_task = ctx.repo.update_task(id, name, status).await?;
     Ok(Json(updated_task))
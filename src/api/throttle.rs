@@ -0,0 +1,145 @@
+use crate::{Error, Result};
+use moka::{ops::compute::Op, sync::Cache};
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Most accounts and addresses tracked at once; the least recently failed are dropped first.
+const MAX_TRACKED: u64 = 100_000;
+
+/// Counts failed logins per account and per client address, refusing further attempts once
+/// either reaches its limit until the lockout period passes without new attempts.
+/// Attempts are counted before the password is checked, so concurrent guesses can't all
+/// slip in before the first failure is recorded; a success takes its attempt back.
+/// Counts are kept in memory, so each instance limits separately.
+pub struct LoginThrottle {
+    accounts: Cache<String, u32>,
+    ips: Cache<IpAddr, u32>,
+    max_per_account: u32,
+    max_per_ip: u32,
+}
+
+impl LoginThrottle {
+    /// Create a throttle with limits on failures per account and per address.
+    pub fn new(max_per_account: u32, max_per_ip: u32, lockout: Duration) -> Self {
+        Self {
+            accounts: counters(lockout),
+            ips: counters(lockout),
+            max_per_account,
+            max_per_ip,
+        }
+    }
+
+    /// Count a login attempt as failed until it succeeds, whether or not the account exists.
+    /// Refuses the attempt when the account or address has failed too often.
+    pub fn attempt(&self, username: &str, ip: Option<IpAddr>) -> Result<()> {
+        let account_locked = increment(&self.accounts, username.to_string()) > self.max_per_account;
+        let ip_locked = ip.is_some_and(|ip| increment(&self.ips, ip) > self.max_per_ip);
+        if account_locked || ip_locked {
+            let message = "too many failed logins, try again later";
+            return Err(Error::too_many_requests(message.into()));
+        }
+        Ok(())
+    }
+
+    /// Forget failures for an account after a successful login,
+    /// and take back the attempt counted against the address.
+    pub fn succeeded(&self, username: &str, ip: Option<IpAddr>) {
+        self.accounts.invalidate(username);
+        if let Some(ip) = ip {
+            self.ips
+                .entry(ip)
+                .and_compute_with(|count| match count.map(|c| c.into_value()) {
+                    Some(count) if count > 1 => Op::Put(count - 1),
+                    _ => Op::Remove,
+                });
+        }
+    }
+}
+
+/// Failure counters that expire after the lockout period without new failures.
+fn counters<K: Hash + Eq + Send + Sync + 'static>(lockout: Duration) -> Cache<K, u32> {
+    Cache::builder()
+        .max_capacity(MAX_TRACKED)
+        .time_to_live(lockout)
+        .build()
+}
+
+/// Atomically add one to a counter, restarting its lockout period; returns the new count.
+fn increment<K: Hash + Eq + Send + Sync + 'static>(cache: &Cache<K, u32>, key: K) -> u32 {
+    cache
+        .entry(key)
+        .and_upsert_with(|count| count.map_or(1, |c| c.into_value() + 1))
+        .into_value()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::thread;
+
+    const LOCKOUT: Duration = Duration::from_secs(60);
+
+    fn ip(last: u8) -> Option<IpAddr> {
+        Some(IpAddr::from([192, 0, 2, last]))
+    }
+
+    fn refused(result: Result<()>) -> bool {
+        matches!(result, Err(Error::TooManyRequests { .. }))
+    }
+
+    #[test]
+    fn refuses_an_account_past_its_limit_from_any_address() {
+        let throttle = LoginThrottle::new(3, 100, LOCKOUT);
+        for last in 1..=3 {
+            throttle.attempt("alice", ip(last)).unwrap();
+        }
+        assert!(refused(throttle.attempt("alice", ip(4))));
+        throttle.attempt("bob", ip(4)).unwrap();
+    }
+
+    #[test]
+    fn refuses_an_address_past_its_limit_for_any_account() {
+        let throttle = LoginThrottle::new(100, 3, LOCKOUT);
+        for name in ["a", "b", "c"] {
+            throttle.attempt(name, ip(1)).unwrap();
+        }
+        assert!(refused(throttle.attempt("d", ip(1))));
+        throttle.attempt("d", ip(2)).unwrap();
+        throttle.attempt("e", None).unwrap();
+    }
+
+    #[test]
+    fn a_success_takes_back_its_attempt() {
+        let throttle = LoginThrottle::new(2, 2, LOCKOUT);
+        throttle.attempt("alice", ip(1)).unwrap();
+        throttle.attempt("alice", ip(1)).unwrap();
+        throttle.succeeded("alice", ip(1));
+        // The account is forgotten and the address is back to one failure
+        throttle.attempt("alice", ip(1)).unwrap();
+        assert!(refused(throttle.attempt("bob", ip(1))));
+    }
+
+    #[test]
+    fn parallel_attempts_are_counted_before_verification() {
+        let throttle = LoginThrottle::new(5, 100, LOCKOUT);
+        let barrier = Barrier::new(20);
+        let allowed = thread::scope(|s| {
+            let handles: Vec<_> = (0..20)
+                .map(|i| {
+                    let (throttle, barrier) = (&throttle, &barrier);
+                    s.spawn(move || {
+                        barrier.wait();
+                        throttle.attempt("alice", ip(i)).is_ok()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|h| h.join().unwrap().then_some(()))
+                .count()
+        });
+        assert_eq!(allowed, 5);
+    }
+}
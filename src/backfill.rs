@@ -0,0 +1,76 @@
+use crate::{
+    config::Config,
+    db::{pool::PgPoolBuilder, sql},
+    Result,
+};
+use std::time::Instant;
+
+/// Usage for the backfill-owner subcommand.
+const USAGE: &str = "usage: backfill-owner --owner <subject> [--batch-size <n>]";
+
+/// Options for the backfill-owner subcommand.
+#[derive(Debug)]
+pub struct BackfillOptions {
+    pub owner: String,
+    pub batch_size: i64,
+}
+
+impl BackfillOptions {
+    /// Parse options from `--name value` command line arguments.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let (mut owner, mut batch_size) = (None, 10_000);
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{}: missing value", flag))?;
+            let invalid = || format!("{}: invalid value: {}", flag, value);
+            match flag.as_str() {
+                "--owner" => owner = Some(value.trim().to_string()),
+                "--batch-size" => batch_size = value.parse().map_err(|_| invalid())?,
+                _ => return Err(format!("unknown flag: {}", flag)),
+            }
+        }
+        match owner {
+            Some(owner) if !owner.is_empty() => Ok(Self {
+                owner,
+                batch_size: batch_size.max(1),
+            }),
+            _ => Err(USAGE.into()),
+        }
+    }
+}
+
+/// Give every story without an owner the given owner, in batches so no single
+/// transaction locks the whole table.
+pub async fn run(config: &Config, opts: BackfillOptions) -> Result<()> {
+    tracing::info!("backfilling owners: {:?}", opts);
+
+    let stmt = sql::for_config(sql::stories::ASSIGN_OWNER, config);
+
+    let pool = PgPoolBuilder::build(config).await?;
+    let conn = pool.get().await?;
+    let start = Instant::now();
+    let mut num_stories = 0u64;
+
+    loop {
+        let updated = conn
+            .inner
+            .execute(&*stmt, &[&opts.owner, &opts.batch_size])
+            .await?;
+        if updated == 0 {
+            break;
+        }
+        num_stories += updated;
+        tracing::info!("assigned {} stories to {}", num_stories, opts.owner);
+    }
+
+    tracing::info!(
+        "assigned {} stories to {} in {:?}",
+        num_stories,
+        opts.owner,
+        start.elapsed()
+    );
+
+    Ok(())
+}
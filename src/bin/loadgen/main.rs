@@ -0,0 +1,369 @@
+//! Drives a configurable mix of requests against a running server and reports
+//! throughput and latency percentiles per endpoint.
+
+use dotenvy::dotenv;
+use mix::{Mix, Op};
+use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
+use report::Stats;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, AUTHORIZATION},
+    Client, StatusCode,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time::MissedTickBehavior;
+
+mod mix;
+mod report;
+
+/// Default mix of operations, weighted towards reads.
+const DEFAULT_MIX: &str = "create_story=1,create_task=3,get_story=4,list_stories=4,\
+list_tasks=4,patch_task=2,delete_task=1,delete_story=1";
+
+/// Load generator settings, read from env vars.
+#[derive(Clone, Debug)]
+struct Settings {
+    target: String,
+    concurrency: usize,
+    duration: Duration,
+    rate: u64,
+    mix: Mix,
+    json_out: Option<String>,
+    http2: bool,
+    /// Credentials sent with every request, marked sensitive so they aren't logged.
+    headers: HeaderMap,
+}
+
+impl Settings {
+    /// Load settings from env vars.
+    fn load() -> Self {
+        let target = env::var("LOADGEN_TARGET").unwrap_or("http://localhost:8080".into());
+        let concurrency = env_or("LOADGEN_CONCURRENCY", 64);
+        let duration = Duration::from_secs(env_or("LOADGEN_DURATION_SECS", 30));
+        let rate = env_or("LOADGEN_RATE", 0);
+        let mix = env::var("LOADGEN_MIX")
+            .unwrap_or(DEFAULT_MIX.into())
+            .parse()
+            .expect("LOADGEN_MIX could not be parsed");
+        let json_out = env::var("LOADGEN_JSON_OUT").ok();
+        let http2 = env_or("LOADGEN_HTTP2", false);
+        let mut headers = HeaderMap::new();
+        if let Ok(key) = env::var("LOADGEN_API_KEY") {
+            headers.insert("x-api-key", sensitive(&key, "LOADGEN_API_KEY"));
+        }
+        if let Ok(token) = env::var("LOADGEN_BEARER_TOKEN") {
+            let value = sensitive(&format!("Bearer {}", token), "LOADGEN_BEARER_TOKEN");
+            headers.insert(AUTHORIZATION, value);
+        }
+        Self {
+            target: target.trim_end_matches('/').to_string(),
+            concurrency: concurrency.max(1),
+            duration,
+            rate,
+            mix,
+            json_out,
+            http2,
+            headers,
+        }
+    }
+}
+
+/// A header value that is redacted when printed.
+fn sensitive(value: &str, key: &str) -> HeaderValue {
+    let mut value =
+        HeaderValue::from_str(value).unwrap_or_else(|_| panic!("{} could not be parsed", key));
+    value.set_sensitive(true);
+    value
+}
+
+/// Parse an env var, or fall back to a default when it is not set.
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    match env::var(key) {
+        Ok(s) => s
+            .parse()
+            .unwrap_or_else(|_| panic!("{} could not be parsed", key)),
+        Err(_) => default,
+    }
+}
+
+/// Ids created during the run, shared by all workers.
+#[derive(Default)]
+struct Known {
+    stories: Mutex<Vec<i32>>,
+    tasks: Mutex<Vec<(i32, i32)>>,
+}
+
+impl Known {
+    fn random_story<R: Rng>(&self, rng: &mut R) -> Option<i32> {
+        self.stories.lock().unwrap().choose(rng).copied()
+    }
+
+    fn random_task<R: Rng>(&self, rng: &mut R) -> Option<i32> {
+        self.tasks.lock().unwrap().choose(rng).map(|(id, _)| *id)
+    }
+
+    fn take_story<R: Rng>(&self, rng: &mut R) -> Option<i32> {
+        let mut stories = self.stories.lock().unwrap();
+        if stories.is_empty() {
+            return None;
+        }
+        let index = rng.gen_range(0..stories.len());
+        let id = stories.swap_remove(index);
+        self.tasks.lock().unwrap().retain(|(_, sid)| *sid != id);
+        Some(id)
+    }
+
+    fn take_task<R: Rng>(&self, rng: &mut R) -> Option<i32> {
+        let mut tasks = self.tasks.lock().unwrap();
+        if tasks.is_empty() {
+            return None;
+        }
+        let index = rng.gen_range(0..tasks.len());
+        Some(tasks.swap_remove(index).0)
+    }
+}
+
+/// Fields of created objects needed to drive later requests.
+#[derive(Deserialize)]
+struct Created {
+    id: i32,
+}
+
+/// Paging fields of list responses.
+#[derive(Deserialize)]
+struct Paged {
+    next_page: Option<String>,
+}
+
+/// Per-worker state for a single simulated client.
+struct Worker {
+    client: Client,
+    settings: Arc<Settings>,
+    known: Arc<Known>,
+    rng: SmallRng,
+    stories_page: Option<String>,
+    tasks_page: Option<(i32, String)>,
+    counter: u64,
+}
+
+impl Worker {
+    /// Issue requests until the deadline, recording stats for each.
+    async fn run(mut self, deadline: Instant) -> Stats {
+        let mut stats = Stats::default();
+        let mut ticker = (self.settings.rate > 0).then(|| {
+            let per_worker = self.settings.rate as f64 / self.settings.concurrency as f64;
+            let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / per_worker));
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Burst);
+            ticker
+        });
+
+        while Instant::now() < deadline {
+            // At a fixed rate, latency counts from when the request was due, not when it
+            // was sent, so a slow response delaying the next requests isn't hidden
+            let start = match ticker.as_mut() {
+                Some(ticker) => ticker.tick().await.into_std(),
+                None => Instant::now(),
+            };
+            let op = self.settings.mix.pick(&mut self.rng);
+            let (op, ok) = match self.issue(op).await {
+                Ok((op, status)) => (op, status.is_success()),
+                Err(err) => {
+                    tracing::debug!("{} failed: {}", op.name(), err);
+                    (op, false)
+                }
+            };
+            stats.record(op, start.elapsed(), ok);
+        }
+
+        stats
+    }
+
+    /// Issue a single request, falling back to creates when there is nothing to act on.
+    async fn issue(&mut self, op: Op) -> reqwest::Result<(Op, StatusCode)> {
+        let base = &self.settings.target;
+        self.counter += 1;
+
+        match op {
+            Op::CreateStory => self.create_story().await,
+            Op::CreateTask => match self.known.random_story(&mut self.rng) {
+                Some(story_id) => {
+                    let body =
+                        json!({ "name": format!("Task {}", self.counter), "story_id": story_id });
+                    let res = self
+                        .client
+                        .post(format!("{}/tasks", base))
+                        .json(&body)
+                        .send()
+                        .await?;
+                    let status = res.status();
+                    if status.is_success() {
+                        let task: Created = res.json().await?;
+                        self.known.tasks.lock().unwrap().push((task.id, story_id));
+                    }
+                    Ok((op, status))
+                }
+                None => self.create_story().await,
+            },
+            Op::GetStory => match self.known.random_story(&mut self.rng) {
+                Some(id) => {
+                    let res = self
+                        .client
+                        .get(format!("{}/stories/{}", base, id))
+                        .send()
+                        .await?;
+                    Ok((op, drain(res).await?))
+                }
+                None => self.create_story().await,
+            },
+            Op::ListStories => {
+                let mut req = self.client.get(format!("{}/stories", base));
+                if let Some(token) = self.stories_page.take() {
+                    req = req.query(&[("page_token", token)]);
+                }
+                let res = req.send().await?;
+                let status = res.status();
+                if status.is_success() {
+                    self.stories_page = res.json::<Paged>().await?.next_page;
+                }
+                Ok((op, status))
+            }
+            Op::ListTasks => {
+                let (story_id, token) = match self.tasks_page.take() {
+                    Some((story_id, token)) => (story_id, Some(token)),
+                    None => match self.known.random_story(&mut self.rng) {
+                        Some(story_id) => (story_id, None),
+                        None => return self.create_story().await,
+                    },
+                };
+                let mut req = self
+                    .client
+                    .get(format!("{}/stories/{}/tasks", base, story_id));
+                if let Some(token) = token {
+                    req = req.query(&[("page_token", token)]);
+                }
+                let res = req.send().await?;
+                let status = res.status();
+                if status.is_success() {
+                    let page: Paged = res.json().await?;
+                    self.tasks_page = page.next_page.map(|token| (story_id, token));
+                }
+                Ok((op, status))
+            }
+            Op::PatchTask => match self.known.random_task(&mut self.rng) {
+                Some(id) => {
+                    let status = if self.rng.gen_bool(0.5) {
+                        "complete"
+                    } else {
+                        "incomplete"
+                    };
+                    let body = json!({ "status": status });
+                    let res = self
+                        .client
+                        .patch(format!("{}/tasks/{}", base, id))
+                        .json(&body)
+                        .send()
+                        .await?;
+                    Ok((op, drain(res).await?))
+                }
+                None => self.create_story().await,
+            },
+            Op::DeleteTask => match self.known.take_task(&mut self.rng) {
+                Some(id) => {
+                    let res = self
+                        .client
+                        .delete(format!("{}/tasks/{}", base, id))
+                        .send()
+                        .await?;
+                    Ok((op, drain(res).await?))
+                }
+                None => self.create_story().await,
+            },
+            Op::DeleteStory => match self.known.take_story(&mut self.rng) {
+                Some(id) => {
+                    let res = self
+                        .client
+                        .delete(format!("{}/stories/{}", base, id))
+                        .send()
+                        .await?;
+                    Ok((op, drain(res).await?))
+                }
+                None => self.create_story().await,
+            },
+        }
+    }
+
+    /// Create a story and remember its id.
+    async fn create_story(&mut self) -> reqwest::Result<(Op, StatusCode)> {
+        let body = json!({ "name": format!("Story {}", self.counter) });
+        let url = format!("{}/stories", self.settings.target);
+        let res = self.client.post(url).json(&body).send().await?;
+        let status = res.status();
+        if status.is_success() {
+            let story: Created = res.json().await?;
+            self.known.stories.lock().unwrap().push(story.id);
+        }
+        Ok((Op::CreateStory, status))
+    }
+}
+
+/// Read the response body so the connection can be reused.
+async fn drain(res: reqwest::Response) -> reqwest::Result<StatusCode> {
+    let status = res.status();
+    res.bytes().await?;
+    Ok(status)
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv().ok();
+    tracing_subscriber::fmt::init();
+
+    let settings = Arc::new(Settings::load());
+    tracing::info!("Loaded settings = {:?}", settings);
+
+    // With HTTP/2 all workers share multiplexed connections instead of one each
+    let mut builder = Client::builder()
+        .pool_max_idle_per_host(settings.concurrency)
+        .default_headers(settings.headers.clone());
+    if settings.http2 {
+        builder = builder.http2_prior_knowledge();
+    }
+    let client = builder.build().expect("failed to build http client");
+    let known = Arc::new(Known::default());
+
+    let start = Instant::now();
+    let deadline = start + settings.duration;
+    let workers: Vec<_> = (0..settings.concurrency)
+        .map(|i| {
+            let worker = Worker {
+                client: client.clone(),
+                settings: Arc::clone(&settings),
+                known: Arc::clone(&known),
+                rng: SmallRng::seed_from_u64(i as u64),
+                stories_page: None,
+                tasks_page: None,
+                counter: (i as u64) << 32,
+            };
+            tokio::spawn(worker.run(deadline))
+        })
+        .collect();
+
+    let mut stats = Stats::default();
+    for worker in workers {
+        stats.merge(worker.await.expect("worker panicked"));
+    }
+    let report = stats.report(start.elapsed());
+
+    print!("{}", report.to_text());
+    if let Some(path) = settings.json_out.as_ref() {
+        let json = serde_json::to_string_pretty(&report).expect("failed to serialize report");
+        if path == "-" {
+            println!("{}", json);
+        } else {
+            std::fs::write(path, json).expect("failed to write json report");
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use rand::Rng;
+use std::str::FromStr;
+
+/// Endpoint operations the load generator can issue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Op {
+    CreateStory,
+    CreateTask,
+    GetStory,
+    ListStories,
+    ListTasks,
+    PatchTask,
+    DeleteTask,
+    DeleteStory,
+}
+
+impl Op {
+    /// Name used in mixes and reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::CreateStory => "create_story",
+            Op::CreateTask => "create_task",
+            Op::GetStory => "get_story",
+            Op::ListStories => "list_stories",
+            Op::ListTasks => "list_tasks",
+            Op::PatchTask => "patch_task",
+            Op::DeleteTask => "delete_task",
+            Op::DeleteStory => "delete_story",
+        }
+    }
+}
+
+impl FromStr for Op {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "create_story" => Ok(Op::CreateStory),
+            "create_task" => Ok(Op::CreateTask),
+            "get_story" => Ok(Op::GetStory),
+            "list_stories" => Ok(Op::ListStories),
+            "list_tasks" => Ok(Op::ListTasks),
+            "patch_task" => Ok(Op::PatchTask),
+            "delete_task" => Ok(Op::DeleteTask),
+            "delete_story" => Ok(Op::DeleteStory),
+            _ => Err(format!("unknown operation: {}", s)),
+        }
+    }
+}
+
+/// A weighted mix of operations, e.g. `create_story=1,list_stories=4`.
+#[derive(Clone, Debug)]
+pub struct Mix {
+    weights: Vec<(Op, u32)>,
+    total: u32,
+}
+
+impl Mix {
+    /// Pick an operation at random according to the mix weights.
+    pub fn pick<R: Rng>(&self, rng: &mut R) -> Op {
+        let mut n = rng.gen_range(0..self.total);
+        for (op, weight) in &self.weights {
+            if n < *weight {
+                return *op;
+            }
+            n -= weight;
+        }
+        self.weights[0].0
+    }
+}
+
+impl FromStr for Mix {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = Vec::new();
+        for entry in s.split(',').filter(|e| !e.trim().is_empty()) {
+            let (op, weight) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected op=weight: {}", entry))?;
+            let weight: u32 = weight
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid weight: {}", entry))?;
+            if weight > 0 {
+                weights.push((op.parse()?, weight));
+            }
+        }
+        let total = weights.iter().map(|(_, w)| w).sum();
+        if total == 0 {
+            return Err("mix has no operations".into());
+        }
+        Ok(Self { weights, total })
+    }
+}
@@ -0,0 +1,148 @@
+use crate::mix::Op;
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Latency and outcome counts for a single endpoint.
+pub struct EndpointStats {
+    latency_us: Histogram<u64>,
+    errors: u64,
+}
+
+impl EndpointStats {
+    fn new() -> Self {
+        Self {
+            latency_us: Histogram::new_with_bounds(1, 60_000_000, 3).expect("histogram bounds"),
+            errors: 0,
+        }
+    }
+}
+
+/// Stats collected by a worker, keyed by operation.
+#[derive(Default)]
+pub struct Stats {
+    endpoints: BTreeMap<Op, EndpointStats>,
+}
+
+impl Stats {
+    /// Record the outcome of a single request.
+    pub fn record(&mut self, op: Op, latency: Duration, ok: bool) {
+        let stats = self.endpoints.entry(op).or_insert_with(EndpointStats::new);
+        stats
+            .latency_us
+            .saturating_record(latency.as_micros() as u64);
+        if !ok {
+            stats.errors += 1;
+        }
+    }
+
+    /// Fold another worker's stats into these.
+    pub fn merge(&mut self, other: Stats) {
+        for (op, theirs) in other.endpoints {
+            let ours = self.endpoints.entry(op).or_insert_with(EndpointStats::new);
+            ours.latency_us
+                .add(&theirs.latency_us)
+                .expect("histograms share bounds");
+            ours.errors += theirs.errors;
+        }
+    }
+
+    /// Summarize the stats over the run duration.
+    pub fn report(&self, elapsed: Duration) -> Report {
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        let endpoints: BTreeMap<_, _> = self
+            .endpoints
+            .iter()
+            .map(|(op, stats)| (op.name(), EndpointReport::new(stats, secs)))
+            .collect();
+        let requests = endpoints.values().map(|e| e.requests).sum::<u64>();
+        let errors = endpoints.values().map(|e| e.errors).sum();
+        Report {
+            elapsed_secs: secs,
+            requests,
+            errors,
+            throughput_rps: requests as f64 / secs,
+            endpoints,
+        }
+    }
+}
+
+/// Latency percentiles in microseconds.
+#[derive(Serialize)]
+pub struct Latency {
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+/// Summary for a single endpoint.
+#[derive(Serialize)]
+pub struct EndpointReport {
+    pub requests: u64,
+    pub errors: u64,
+    pub throughput_rps: f64,
+    pub latency_us: Latency,
+}
+
+impl EndpointReport {
+    fn new(stats: &EndpointStats, secs: f64) -> Self {
+        let h = &stats.latency_us;
+        Self {
+            requests: h.len(),
+            errors: stats.errors,
+            throughput_rps: h.len() as f64 / secs,
+            latency_us: Latency {
+                mean: h.mean(),
+                p50: h.value_at_quantile(0.5),
+                p90: h.value_at_quantile(0.9),
+                p99: h.value_at_quantile(0.99),
+                p999: h.value_at_quantile(0.999),
+                max: h.max(),
+            },
+        }
+    }
+}
+
+/// Summary for a whole run.
+#[derive(Serialize)]
+pub struct Report {
+    pub elapsed_secs: f64,
+    pub requests: u64,
+    pub errors: u64,
+    pub throughput_rps: f64,
+    pub endpoints: BTreeMap<&'static str, EndpointReport>,
+}
+
+impl Report {
+    /// Render the report as a plain text table.
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "{} requests in {:.1}s, {} errors, {:.1} req/s\n\n",
+            self.requests, self.elapsed_secs, self.errors, self.throughput_rps
+        );
+        out.push_str(&format!(
+            "{:<14} {:>9} {:>7} {:>10} {:>9} {:>9} {:>9} {:>9} {:>9}\n",
+            "endpoint",
+            "requests",
+            "errors",
+            "req/s",
+            "p50(us)",
+            "p90(us)",
+            "p99(us)",
+            "p999(us)",
+            "max(us)"
+        ));
+        for (name, e) in &self.endpoints {
+            let l = &e.latency_us;
+            out.push_str(&format!(
+                "{:<14} {:>9} {:>7} {:>10.1} {:>9} {:>9} {:>9} {:>9} {:>9}\n",
+                name, e.requests, e.errors, e.throughput_rps, l.p50, l.p90, l.p99, l.p999, l.max
+            ));
+        }
+        out
+    }
+}
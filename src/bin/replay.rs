@@ -0,0 +1,276 @@
+//! Re-issues recorded requests against a server and diffs the responses with the
+//! recorded ones.
+
+use bb8_todos::api::record::{RecordedBody, Recording};
+use dotenvy::dotenv;
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    Client, Method,
+};
+use serde_json::Value;
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// Longest body shown when printing a diff.
+const MAX_SHOWN: usize = 200;
+
+/// Replay settings, read from env vars.
+#[derive(Debug)]
+struct Settings {
+    file: String,
+    target: String,
+    speed: f64,
+    compare_bodies: bool,
+    ignore_fields: Vec<String>,
+    max_diffs: usize,
+    /// Credentials sent with every request, marked sensitive so they aren't logged.
+    credentials: HeaderMap,
+}
+
+impl Settings {
+    /// Load settings from env vars.
+    fn load() -> Self {
+        let mut credentials = HeaderMap::new();
+        if let Ok(key) = env::var("REPLAY_API_KEY") {
+            credentials.insert("x-api-key", sensitive(&key, "REPLAY_API_KEY"));
+        }
+        if let Ok(value) = env::var("REPLAY_AUTHORIZATION") {
+            let value = sensitive(&value, "REPLAY_AUTHORIZATION");
+            credentials.insert("authorization", value);
+        }
+        Self {
+            file: env::var("REPLAY_FILE").unwrap_or("requests.jsonl".into()),
+            target: env::var("REPLAY_TARGET")
+                .unwrap_or("http://localhost:8080".into())
+                .trim_end_matches('/')
+                .to_string(),
+            speed: env_or("REPLAY_SPEED", 1.0),
+            compare_bodies: env_or("REPLAY_COMPARE_BODIES", true),
+            ignore_fields: env::var("REPLAY_IGNORE_FIELDS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+                .collect(),
+            max_diffs: env_or("REPLAY_MAX_DIFFS", 20),
+            credentials,
+        }
+    }
+}
+
+/// A header value that is redacted when printed.
+fn sensitive(value: &str, key: &str) -> HeaderValue {
+    let mut value =
+        HeaderValue::from_str(value).unwrap_or_else(|_| panic!("{} could not be parsed", key));
+    value.set_sensitive(true);
+    value
+}
+
+/// Parse an env var, or fall back to a default when it is not set.
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    match env::var(key) {
+        Ok(s) => s
+            .parse()
+            .unwrap_or_else(|_| panic!("{} could not be parsed", key)),
+        Err(_) => default,
+    }
+}
+
+/// The outcome of replaying a single recording.
+enum Outcome {
+    Match,
+    Diff(String),
+    Failed(String),
+}
+
+/// Re-issue a recorded request with the given credentials and compare the response.
+async fn replay(
+    client: Client,
+    target: String,
+    credentials: HeaderMap,
+    rec: Recording,
+    compare: Compare,
+) -> Outcome {
+    let mut url = format!("{}{}", target, rec.path);
+    if let Some(query) = rec.query.as_ref() {
+        url = format!("{}?{}", url, query);
+    }
+    let method = match Method::from_bytes(rec.method.as_bytes()) {
+        Ok(method) => method,
+        Err(err) => return Outcome::Failed(format!("{} {}: {}", rec.method, rec.path, err)),
+    };
+    let body = match rec.body.to_bytes() {
+        Ok(body) => body,
+        Err(err) => return Outcome::Failed(format!("{} {}: {}", rec.method, rec.path, err)),
+    };
+
+    let mut req = client.request(method, &url).body(body);
+    for (name, value) in &rec.headers {
+        req = req.header(name, value);
+    }
+    // Replaces any recorded credentials, which have likely expired
+    req = req.headers(credentials);
+    let res = match req.send().await {
+        Ok(res) => res,
+        Err(err) => return Outcome::Failed(format!("{} {}: {}", rec.method, rec.path, err)),
+    };
+    let status = res.status().as_u16();
+    let bytes = match res.bytes().await {
+        Ok(bytes) => bytes,
+        Err(err) => return Outcome::Failed(format!("{} {}: {}", rec.method, rec.path, err)),
+    };
+
+    if status != rec.status {
+        return Outcome::Diff(format!(
+            "{} {}: status {} != recorded {}",
+            rec.method, rec.path, status, rec.status
+        ));
+    }
+    if compare.bodies {
+        let recorded = rec.response_body.to_bytes().unwrap_or_default();
+        if !same_body(&recorded, &bytes, &compare.ignore_fields) {
+            return Outcome::Diff(format!(
+                "{} {}: body differs\n  recorded: {}\n  replayed: {}",
+                rec.method,
+                rec.path,
+                shown(&recorded),
+                shown(&bytes)
+            ));
+        }
+    }
+    Outcome::Match
+}
+
+/// How replayed responses are compared with recorded ones.
+#[derive(Clone)]
+struct Compare {
+    bodies: bool,
+    ignore_fields: Arc<Vec<String>>,
+}
+
+/// Compare bodies as json when both parse, otherwise byte for byte.
+fn same_body(a: &[u8], b: &[u8], ignore_fields: &[String]) -> bool {
+    match (
+        serde_json::from_slice::<Value>(a),
+        serde_json::from_slice::<Value>(b),
+    ) {
+        (Ok(mut a), Ok(mut b)) => {
+            strip(&mut a, ignore_fields);
+            strip(&mut b, ignore_fields);
+            a == b
+        }
+        _ => a == b,
+    }
+}
+
+/// Remove ignored fields, such as generated ids, from json objects at any depth.
+fn strip(value: &mut Value, ignore_fields: &[String]) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|k, _| !ignore_fields.contains(k));
+            map.values_mut().for_each(|v| strip(v, ignore_fields));
+        }
+        Value::Array(values) => values.iter_mut().for_each(|v| strip(v, ignore_fields)),
+        _ => {}
+    }
+}
+
+/// A truncated, printable version of a body.
+fn shown(bytes: &[u8]) -> String {
+    match RecordedBody::new(bytes) {
+        RecordedBody::Empty => "<empty>".into(),
+        RecordedBody::Text(text) | RecordedBody::Base64(text) => {
+            text.chars().take(MAX_SHOWN).collect()
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    dotenv().ok();
+    tracing_subscriber::fmt::init();
+
+    let settings = Settings::load();
+    tracing::info!("Loaded settings = {:?}", settings);
+
+    let file = File::open(&settings.file).expect("failed to open recording file");
+    let mut recordings: Vec<Recording> = BufReader::new(file)
+        .lines()
+        .map(|line| line.expect("failed to read recording file"))
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(&line).expect("invalid recording"))
+        .collect();
+    // Written as requests complete, so put them back in the order they arrived;
+    // the sort is stable, keeping file order for requests in the same millisecond
+    recordings.sort_by_key(|r| r.ts_ms);
+
+    let client = Client::new();
+    let compare = Compare {
+        bodies: settings.compare_bodies,
+        ignore_fields: Arc::new(settings.ignore_fields.clone()),
+    };
+    let start = Instant::now();
+    let first_ts = recordings.first().map(|r| r.ts_ms).unwrap_or_default();
+    let mut outcomes = Vec::with_capacity(recordings.len());
+    let mut pending: Vec<JoinHandle<Outcome>> = Vec::new();
+
+    for rec in recordings {
+        let target = settings.target.clone();
+        let compare = compare.clone();
+        let credentials = settings.credentials.clone();
+        if settings.speed > 0.0 {
+            // Keep the recorded spacing between requests, scaled by the speed
+            let offset = Duration::from_millis(rec.ts_ms.saturating_sub(first_ts));
+            tokio::time::sleep_until((start + offset.div_f64(settings.speed)).into()).await;
+            pending.push(tokio::spawn(replay(
+                client.clone(),
+                target,
+                credentials,
+                rec,
+                compare,
+            )));
+        } else {
+            // As fast as possible, one at a time, in arrival order
+            outcomes.push(replay(client.clone(), target, credentials, rec, compare).await);
+        }
+    }
+    for handle in pending {
+        outcomes.push(handle.await.expect("replay task panicked"));
+    }
+
+    let (mut matched, mut diffs, mut failed) = (0, 0, 0);
+    for outcome in &outcomes {
+        match outcome {
+            Outcome::Match => matched += 1,
+            Outcome::Diff(diff) => {
+                diffs += 1;
+                if diffs <= settings.max_diffs {
+                    println!("{}", diff);
+                }
+            }
+            Outcome::Failed(err) => {
+                failed += 1;
+                println!("error: {}", err);
+            }
+        }
+    }
+    println!(
+        "{} requests in {:.1}s: {} matched, {} differed, {} failed",
+        outcomes.len(),
+        start.elapsed().as_secs_f64(),
+        matched,
+        diffs,
+        failed
+    );
+
+    if diffs + failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -1,12 +1,81 @@
-use std::{env, net::SocketAddr};
+use std::{env, net::SocketAddr, str::FromStr};
 use tokio::net::TcpListener;
 
+/// Supported connection pool implementations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DbPoolKind {
+    Bb8,
+    Deadpool,
+    /// A single multiplexed, pipelined connection.
+    Mux,
+}
+
+impl FromStr for DbPoolKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "bb8" => Ok(Self::Bb8),
+            "deadpool" => Ok(Self::Deadpool),
+            "mux" => Ok(Self::Mux),
+            _ => Err(format!("unknown pool kind: {}", s)),
+        }
+    }
+}
+
 /// Configuration settings
 #[derive(Clone, Debug)]
 pub struct Config {
     pub listen_addr: String,
+    pub listen_unix_path: Option<String>,
+    pub listen_unix_mode: u32,
     pub db_url: String,
+    pub db_pool: DbPoolKind,
     pub db_max_pool_size: u32,
+    pub db_pool_timeout_secs: u64,
+    pub db_prepare_eager: bool,
+    pub db_pgbouncer: bool,
+    pub db_schema: String,
+    pub db_replica_url: Option<String>,
+    pub db_replica_max_lag_ms: u64,
+    pub db_replica_check_secs: u64,
+    pub read_your_writes_secs: u64,
+    pub record_path: Option<String>,
+    pub record_headers: Vec<String>,
+    pub write_batch_size: usize,
+    pub write_batch_window_ms: u64,
+    pub read_cache_capacity: u64,
+    pub read_cache_ttl_secs: u64,
+    pub read_cache_notify: bool,
+    pub cache_control_list: String,
+    pub cache_control_detail: String,
+    pub max_request_body_bytes: usize,
+    pub compression_min_bytes: u16,
+    pub http1_keep_alive: bool,
+    pub http2_max_concurrent_streams: u32,
+    pub http2_keep_alive_secs: u64,
+    pub http2_keep_alive_timeout_secs: u64,
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    pub tls_client_ca_path: Option<String>,
+    pub tls_reload_secs: u64,
+    pub cors_allowed_origins: Vec<String>,
+    pub cors_allowed_methods: Vec<String>,
+    pub cors_allowed_headers: Vec<String>,
+    pub cors_expose_headers: Vec<String>,
+    pub cors_allow_credentials: bool,
+    pub cors_max_age_secs: u64,
+    pub auth_enabled: bool,
+    pub jwt_jwks_path: Option<String>,
+    pub jwt_jwks_url: Option<String>,
+    pub jwt_issuer: String,
+    pub jwt_audience: String,
+    pub jwt_jwks_refresh_secs: u64,
+    pub jwt_leeway_secs: u64,
+    pub session_ttl_secs: u64,
+    pub session_cookie_secure: bool,
+    pub login_max_failures_per_account: u32,
+    pub login_max_failures_per_ip: u32,
+    pub login_lockout_secs: u64,
 }
 
 /// Default for config just calls basic constructor
@@ -23,21 +92,286 @@ impl Config {
         let port = env::var("HTTP_SERVER_PORT").unwrap_or("8080".into());
         let listen_addr = format!("0.0.0.0:{}", port);
 
+        // unix socket listener instead of tcp; the mode is octal, e.g. 660
+        let listen_unix_path = env::var("LISTEN_UNIX_PATH").ok();
+        let mut listen_unix_mode = 0o660;
+        if let Ok(s) = env::var("LISTEN_UNIX_MODE") {
+            listen_unix_mode =
+                u32::from_str_radix(&s, 8).expect("LISTEN_UNIX_MODE could not be parsed")
+        }
+
         // db connection
         let db_url = env::var("DATABASE_URL").expect("DATABASE_URL not set");
 
         // db pool
+        let mut db_pool = DbPoolKind::Bb8;
+        if let Ok(s) = env::var("DATABASE_POOL") {
+            db_pool = s.parse().expect("DATABASE_POOL could not be parsed")
+        }
         let mut db_max_pool_size = num_cpus::get() as u32;
         if let Ok(s) = env::var("DATABASE_MAX_POOL_SIZE") {
             db_max_pool_size = s
                 .parse()
                 .expect("DATABASE_MAX_POOL_SIZE could not be parsed")
         }
+        // how long a checkout waits for a connection, the same for every pool
+        let mut db_pool_timeout_secs = 30;
+        if let Ok(s) = env::var("DATABASE_POOL_TIMEOUT_SECS") {
+            db_pool_timeout_secs = s
+                .parse()
+                .expect("DATABASE_POOL_TIMEOUT_SECS could not be parsed")
+        }
+
+        // prepare all statements when connections are created
+        let mut db_prepare_eager = true;
+        if let Ok(s) = env::var("DATABASE_PREPARE_EAGER") {
+            db_prepare_eager = s
+                .parse()
+                .expect("DATABASE_PREPARE_EAGER could not be parsed")
+        }
+
+        // pgbouncer transaction pooling compatibility
+        let mut db_pgbouncer = false;
+        if let Ok(s) = env::var("DATABASE_PGBOUNCER") {
+            db_pgbouncer = s.parse().expect("DATABASE_PGBOUNCER could not be parsed")
+        }
+        let db_schema = env::var("DATABASE_SCHEMA").unwrap_or("public".into());
+
+        // read replica routing
+        let db_replica_url = env::var("DATABASE_REPLICA_URL").ok();
+        let mut db_replica_max_lag_ms = 1000;
+        if let Ok(s) = env::var("DATABASE_REPLICA_MAX_LAG_MS") {
+            db_replica_max_lag_ms = s
+                .parse()
+                .expect("DATABASE_REPLICA_MAX_LAG_MS could not be parsed")
+        }
+        let mut db_replica_check_secs = 5;
+        if let Ok(s) = env::var("DATABASE_REPLICA_CHECK_SECS") {
+            db_replica_check_secs = s
+                .parse()
+                .expect("DATABASE_REPLICA_CHECK_SECS could not be parsed")
+        }
+        let mut read_your_writes_secs = 5;
+        if let Ok(s) = env::var("READ_YOUR_WRITES_SECS") {
+            read_your_writes_secs = s
+                .parse()
+                .expect("READ_YOUR_WRITES_SECS could not be parsed")
+        }
+
+        // request recording
+        let record_path = env::var("RECORD_REQUESTS_PATH").ok();
+        let record_headers = env_list("RECORD_REQUESTS_HEADERS", "content-type,accept")
+            .into_iter()
+            .map(|h| h.to_lowercase())
+            .collect();
+
+        // group commit of inserts; zero disables batching
+        let mut write_batch_size = 0;
+        if let Ok(s) = env::var("WRITE_BATCH_SIZE") {
+            write_batch_size = s.parse().expect("WRITE_BATCH_SIZE could not be parsed")
+        }
+        let mut write_batch_window_ms = 2;
+        if let Ok(s) = env::var("WRITE_BATCH_WINDOW_MS") {
+            write_batch_window_ms = s
+                .parse()
+                .expect("WRITE_BATCH_WINDOW_MS could not be parsed")
+        }
+
+        // in-memory read cache; zero capacity disables caching
+        let mut read_cache_capacity = 0;
+        if let Ok(s) = env::var("READ_CACHE_CAPACITY") {
+            read_cache_capacity = s.parse().expect("READ_CACHE_CAPACITY could not be parsed")
+        }
+        let mut read_cache_ttl_secs = 5;
+        if let Ok(s) = env::var("READ_CACHE_TTL_SECS") {
+            read_cache_ttl_secs = s.parse().expect("READ_CACHE_TTL_SECS could not be parsed")
+        }
+        let mut read_cache_notify = true;
+        if let Ok(s) = env::var("READ_CACHE_NOTIFY") {
+            read_cache_notify = s.parse().expect("READ_CACHE_NOTIFY could not be parsed")
+        }
+
+        // http caching of story and task reads
+        let cache_control_list = env::var("CACHE_CONTROL_LIST").unwrap_or("no-cache".into());
+        let cache_control_detail = env::var("CACHE_CONTROL_DETAIL").unwrap_or("no-cache".into());
+
+        // request and response bodies
+        let mut max_request_body_bytes = 2 * 1024 * 1024;
+        if let Ok(s) = env::var("MAX_REQUEST_BODY_BYTES") {
+            max_request_body_bytes = s
+                .parse()
+                .expect("MAX_REQUEST_BODY_BYTES could not be parsed")
+        }
+        let mut compression_min_bytes = 1024;
+        if let Ok(s) = env::var("COMPRESSION_MIN_BYTES") {
+            compression_min_bytes = s
+                .parse()
+                .expect("COMPRESSION_MIN_BYTES could not be parsed")
+        }
+
+        // protocol settings
+        let mut http1_keep_alive = true;
+        if let Ok(s) = env::var("HTTP1_KEEP_ALIVE") {
+            http1_keep_alive = s.parse().expect("HTTP1_KEEP_ALIVE could not be parsed")
+        }
+        let mut http2_max_concurrent_streams = 200;
+        if let Ok(s) = env::var("HTTP2_MAX_CONCURRENT_STREAMS") {
+            http2_max_concurrent_streams = s
+                .parse()
+                .expect("HTTP2_MAX_CONCURRENT_STREAMS could not be parsed")
+        }
+        let mut http2_keep_alive_secs = 0;
+        if let Ok(s) = env::var("HTTP2_KEEP_ALIVE_SECS") {
+            http2_keep_alive_secs = s
+                .parse()
+                .expect("HTTP2_KEEP_ALIVE_SECS could not be parsed")
+        }
+        let mut http2_keep_alive_timeout_secs = 20;
+        if let Ok(s) = env::var("HTTP2_KEEP_ALIVE_TIMEOUT_SECS") {
+            http2_keep_alive_timeout_secs = s
+                .parse()
+                .expect("HTTP2_KEEP_ALIVE_TIMEOUT_SECS could not be parsed")
+        }
+
+        // tls termination; client certificates are required when a client CA is set
+        let tls_cert_path = env::var("TLS_CERT_PATH").ok();
+        let tls_key_path = env::var("TLS_KEY_PATH").ok();
+        let tls_client_ca_path = env::var("TLS_CLIENT_CA_PATH").ok();
+        let mut tls_reload_secs = 30;
+        if let Ok(s) = env::var("TLS_RELOAD_SECS") {
+            tls_reload_secs = s.parse().expect("TLS_RELOAD_SECS could not be parsed")
+        }
+
+        // cors for browser clients; disabled unless origins are set
+        let cors_allowed_origins = env_list("CORS_ALLOWED_ORIGINS", "");
+        let cors_allowed_methods = env_list("CORS_ALLOWED_METHODS", "GET,POST,PATCH,DELETE");
+        let cors_allowed_headers = env_list(
+            "CORS_ALLOWED_HEADERS",
+            "authorization,content-type,if-none-match,x-api-key,x-last-write",
+        );
+        let cors_expose_headers = env_list("CORS_EXPOSE_HEADERS", "etag,x-last-write");
+        let mut cors_allow_credentials = false;
+        if let Ok(s) = env::var("CORS_ALLOW_CREDENTIALS") {
+            cors_allow_credentials = s
+                .parse()
+                .expect("CORS_ALLOW_CREDENTIALS could not be parsed")
+        }
+        if cors_allow_credentials && cors_allowed_origins.iter().any(|o| o == "*") {
+            panic!("CORS_ALLOW_CREDENTIALS requires explicit CORS_ALLOWED_ORIGINS, not *");
+        }
+        let mut cors_max_age_secs = 600;
+        if let Ok(s) = env::var("CORS_MAX_AGE_SECS") {
+            cors_max_age_secs = s.parse().expect("CORS_MAX_AGE_SECS could not be parsed")
+        }
+
+        // api key authentication; every route but status checks requires a key when enabled
+        let mut auth_enabled = false;
+        if let Ok(s) = env::var("AUTH_ENABLED") {
+            auth_enabled = s.parse().expect("AUTH_ENABLED could not be parsed")
+        }
+
+        // jwt bearer tokens, verified with keys from a JWKS file or url
+        let jwt_jwks_path = env::var("JWT_JWKS_PATH").ok();
+        let jwt_jwks_url = env::var("JWT_JWKS_URL").ok();
+        let jwt_issuer = env::var("JWT_ISSUER").unwrap_or_default();
+        let jwt_audience = env::var("JWT_AUDIENCE").unwrap_or_default();
+        let jwks_configured = jwt_jwks_path.is_some() || jwt_jwks_url.is_some();
+        if jwks_configured && (jwt_issuer.is_empty() || jwt_audience.is_empty()) {
+            panic!("JWT_ISSUER and JWT_AUDIENCE are required with a JWKS");
+        }
+        let mut jwt_jwks_refresh_secs = 300;
+        if let Ok(s) = env::var("JWT_JWKS_REFRESH_SECS") {
+            jwt_jwks_refresh_secs = s
+                .parse()
+                .expect("JWT_JWKS_REFRESH_SECS could not be parsed")
+        }
+        let mut jwt_leeway_secs = 30;
+        if let Ok(s) = env::var("JWT_LEEWAY_SECS") {
+            jwt_leeway_secs = s.parse().expect("JWT_LEEWAY_SECS could not be parsed")
+        }
+
+        // password login sessions, kept in a cookie
+        let mut session_ttl_secs = 7 * 24 * 60 * 60;
+        if let Ok(s) = env::var("SESSION_TTL_SECS") {
+            session_ttl_secs = s.parse().expect("SESSION_TTL_SECS could not be parsed")
+        }
+        let mut session_cookie_secure = true;
+        if let Ok(s) = env::var("SESSION_COOKIE_SECURE") {
+            session_cookie_secure = s
+                .parse()
+                .expect("SESSION_COOKIE_SECURE could not be parsed")
+        }
+
+        // failed logins allowed before further attempts are refused for the lockout period
+        let mut login_max_failures_per_account = 5;
+        if let Ok(s) = env::var("LOGIN_MAX_FAILURES_PER_ACCOUNT") {
+            login_max_failures_per_account = s
+                .parse()
+                .expect("LOGIN_MAX_FAILURES_PER_ACCOUNT could not be parsed")
+        }
+        let mut login_max_failures_per_ip = 20;
+        if let Ok(s) = env::var("LOGIN_MAX_FAILURES_PER_IP") {
+            login_max_failures_per_ip = s
+                .parse()
+                .expect("LOGIN_MAX_FAILURES_PER_IP could not be parsed")
+        }
+        let mut login_lockout_secs = 300;
+        if let Ok(s) = env::var("LOGIN_LOCKOUT_SECS") {
+            login_lockout_secs = s.parse().expect("LOGIN_LOCKOUT_SECS could not be parsed")
+        }
 
         Self {
             listen_addr,
+            listen_unix_path,
+            listen_unix_mode,
             db_url,
+            db_pool,
             db_max_pool_size,
+            db_pool_timeout_secs,
+            db_prepare_eager,
+            db_pgbouncer,
+            db_schema,
+            db_replica_url,
+            db_replica_max_lag_ms,
+            db_replica_check_secs,
+            read_your_writes_secs,
+            record_path,
+            record_headers,
+            write_batch_size,
+            write_batch_window_ms,
+            read_cache_capacity,
+            read_cache_ttl_secs,
+            read_cache_notify,
+            cache_control_list,
+            cache_control_detail,
+            max_request_body_bytes,
+            compression_min_bytes,
+            http1_keep_alive,
+            http2_max_concurrent_streams,
+            http2_keep_alive_secs,
+            http2_keep_alive_timeout_secs,
+            tls_cert_path,
+            tls_key_path,
+            tls_client_ca_path,
+            tls_reload_secs,
+            cors_allowed_origins,
+            cors_allowed_methods,
+            cors_allowed_headers,
+            cors_expose_headers,
+            cors_allow_credentials,
+            cors_max_age_secs,
+            auth_enabled,
+            jwt_jwks_path,
+            jwt_jwks_url,
+            jwt_issuer,
+            jwt_audience,
+            jwt_jwks_refresh_secs,
+            jwt_leeway_secs,
+            session_ttl_secs,
+            session_cookie_secure,
+            login_max_failures_per_account,
+            login_max_failures_per_ip,
+            login_lockout_secs,
         }
     }
 
@@ -52,3 +386,13 @@ impl Config {
             .expect("failed to bind tcp listener")
     }
 }
+
+/// Read a comma separated env var, or a default list when it is not set.
+fn env_list(key: &str, default: &str) -> Vec<String> {
+    env::var(key)
+        .unwrap_or(default.into())
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
@@ -1,35 +1,170 @@
-use crate::Result;
+use crate::{db::sql, db::sql::Stmt, Result};
+use futures::future::try_join_all;
 use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::{collections::BTreeMap, ops::DerefMut};
-use tokio_postgres::{Client, Statement};
+use tokio_postgres::{
+    types::{ToSql, Type},
+    Client, Error as PgError, GenericClient, Row, Statement,
+};
 
-/// Custom postgres connection with prepared statement cache.
+/// Query parameters, as accepted by tokio postgres.
+pub type Params<'a> = [&'a (dyn ToSql + Sync)];
+
+/// How statements are sent to the server.
+#[derive(Clone, Debug)]
+pub enum StatementMode {
+    /// Named statements, prepared once per connection and cached.
+    Named,
+    /// Unnamed statements with schema qualified sql; nothing outlives a transaction,
+    /// so this is safe behind PgBouncer in transaction pooling mode.
+    Unnamed(Arc<BTreeMap<Stmt, Arc<str>>>),
+}
+
+impl StatementMode {
+    /// Create an unnamed statement mode with all sql qualified by a schema.
+    pub fn unnamed(schema: &str) -> Self {
+        let sql = Stmt::ALL
+            .iter()
+            .map(|s| (*s, Arc::from(sql::qualify(s.sql(), schema))))
+            .collect();
+        Self::Unnamed(Arc::new(sql))
+    }
+}
+
+/// A statement ready to be executed on a connection or transaction.
+#[derive(Clone)]
+pub enum Prepared {
+    Named(Statement),
+    Unnamed(Arc<str>, &'static [Type]),
+}
+
+impl Prepared {
+    /// Execute the statement, returning all rows.
+    pub async fn query<C>(&self, client: &C, params: &Params<'_>) -> Result<Vec<Row>>
+    where
+        C: GenericClient + Sync,
+    {
+        let rows = match self {
+            Self::Named(stmt) => client.query(stmt, params).await?,
+            Self::Unnamed(sql, types) => client.query_typed(sql, &typed(params, types)).await?,
+        };
+        Ok(rows)
+    }
+
+    /// Execute the statement, returning at most one row.
+    pub async fn query_opt<C>(&self, client: &C, params: &Params<'_>) -> Result<Option<Row>>
+    where
+        C: GenericClient + Sync,
+    {
+        Ok(self.try_query_opt(client, params).await?)
+    }
+
+    /// Like `query_opt`, but returns the postgres error so callers can map specific codes.
+    pub async fn try_query_opt<C>(
+        &self,
+        client: &C,
+        params: &Params<'_>,
+    ) -> std::result::Result<Option<Row>, PgError>
+    where
+        C: GenericClient + Sync,
+    {
+        match self {
+            Self::Named(stmt) => client.query_opt(stmt, params).await,
+            Self::Unnamed(sql, types) => client.query_typed_opt(sql, &typed(params, types)).await,
+        }
+    }
+
+    /// Execute the statement, returning the number of rows modified.
+    pub async fn execute<C>(&self, client: &C, params: &Params<'_>) -> Result<u64>
+    where
+        C: GenericClient + Sync,
+    {
+        let num_rows = match self {
+            Self::Named(stmt) => client.execute(stmt, params).await?,
+            Self::Unnamed(sql, types) => client.execute_typed(sql, &typed(params, types)).await?,
+        };
+        Ok(num_rows)
+    }
+}
+
+/// Pair up parameters with their types for unnamed statements.
+fn typed<'a>(params: &Params<'a>, types: &[Type]) -> Vec<(&'a (dyn ToSql + Sync), Type)> {
+    params.iter().copied().zip(types.iter().cloned()).collect()
+}
+
+/// Custom postgres connection with a registry of prepared statements.
 /// Prepared statments must be executed by the client that created them.
+/// The registry is behind a lock so a single connection can be shared by many tasks.
 pub struct PgConn {
     pub inner: Client,
-    pub ps_cache: BTreeMap<String, Statement>,
+    mode: StatementMode,
+    statements: Mutex<BTreeMap<Stmt, Statement>>,
+    broken: AtomicBool,
 }
 
 impl PgConn {
     /// Create a new custom postgres connection.
-    pub fn new(inner: Client) -> Self {
+    pub fn new(inner: Client, mode: StatementMode) -> Self {
         Self {
             inner,
-            ps_cache: BTreeMap::new(),
+            mode,
+            statements: Mutex::new(BTreeMap::new()),
+            broken: AtomicBool::new(false),
+        }
+    }
+
+    /// Mark the connection as unusable, e.g. when it may be left inside a transaction,
+    /// so pools discard it instead of handing it out again.
+    pub fn mark_broken(&self) {
+        self.broken.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the connection has been closed or marked broken.
+    pub fn is_broken(&self) -> bool {
+        self.broken.load(Ordering::Relaxed) || self.inner.is_closed()
+    }
+
+    /// Whether statements are prepared and cached on this connection.
+    pub fn is_named(&self) -> bool {
+        matches!(self.mode, StatementMode::Named)
+    }
+
+    /// Prepare all known statements, pipelined over a single round-trip.
+    pub async fn prepare_all(&self) -> Result<(), PgError> {
+        if !self.is_named() {
+            return Ok(());
         }
+        let client = &self.inner;
+        let prepared = try_join_all(Stmt::ALL.iter().map(|s| client.prepare(s.sql()))).await?;
+        *self.registry() = Stmt::ALL.iter().copied().zip(prepared).collect();
+        Ok(())
     }
 
-    /// Helper to prepare and cache sql statements.
-    pub async fn prepare_cache(&mut self, sql: &str) -> Result<Statement> {
-        match self.ps_cache.get(sql) {
-            Some(ps) => Ok(ps.to_owned()),
+    /// Get a statement by key, preparing and caching it if needed.
+    pub async fn statement(&self, key: Stmt) -> Result<Prepared> {
+        let sql = match &self.mode {
+            StatementMode::Unnamed(sql) => {
+                return Ok(Prepared::Unnamed(Arc::clone(&sql[&key]), key.param_types()))
+            }
+            StatementMode::Named => key.sql(),
+        };
+        let cached = self.registry().get(&key).cloned();
+        match cached {
+            Some(ps) => Ok(Prepared::Named(ps)),
             None => {
-                let stmt = self.prepare(sql).await?;
-                self.ps_cache.insert(sql.to_string(), stmt.clone());
-                Ok(stmt)
+                let stmt = self.inner.prepare(sql).await?;
+                self.registry().insert(key, stmt.clone());
+                Ok(Prepared::Named(stmt))
             }
         }
     }
+
+    /// Lock the prepared statement registry; never held across an await.
+    fn registry(&self) -> std::sync::MutexGuard<'_, BTreeMap<Stmt, Statement>> {
+        self.statements.lock().unwrap_or_else(|e| e.into_inner())
+    }
 }
 
 /// Deref pointer calls to the inner tokio postgres client.
@@ -0,0 +1,40 @@
+use super::{init_conn, manager::PgConnManager, PgConn};
+use bb8::ManageConnection;
+use deadpool::managed::{Manager, Metrics, RecycleError, RecycleResult};
+use tokio_postgres::{Error, NoTls};
+
+/// Deadpool manager creating the same custom connections as the bb8 pool.
+pub struct DeadpoolManager {
+    inner: PgConnManager<NoTls>,
+    prepare_eager: bool,
+}
+
+impl DeadpoolManager {
+    /// Create a new deadpool manager wrapping a custom connection manager.
+    pub fn new(inner: PgConnManager<NoTls>, prepare_eager: bool) -> Self {
+        Self {
+            inner,
+            prepare_eager,
+        }
+    }
+}
+
+impl Manager for DeadpoolManager {
+    type Type = PgConn;
+    type Error = Error;
+
+    /// Create and set up a new connection.
+    async fn create(&self) -> Result<PgConn, Error> {
+        let conn = self.inner.connect().await?;
+        init_conn(&conn, self.prepare_eager).await?;
+        Ok(conn)
+    }
+
+    /// Discard connections that have been closed or marked broken.
+    async fn recycle(&self, conn: &mut PgConn, _: &Metrics) -> RecycleResult<Error> {
+        if conn.is_broken() {
+            return Err(RecycleError::message("connection closed or broken"));
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use super::PgConn;
+use super::{PgConn, StatementMode};
 use async_trait::async_trait;
 use bb8::ManageConnection;
 use bb8_postgres::PostgresConnectionManager;
@@ -14,6 +14,7 @@ where
     Tls: MakeTlsConnect<Socket>,
 {
     inner: PostgresConnectionManager<Tls>,
+    mode: StatementMode,
 }
 
 impl<Tls> PgConnManager<Tls>
@@ -21,9 +22,10 @@ where
     Tls: MakeTlsConnect<Socket>,
 {
     /// Create a new custom postgres connection manager.
-    pub fn new(config: Config, tls: Tls) -> Self {
+    pub fn new(config: Config, tls: Tls, mode: StatementMode) -> Self {
         Self {
             inner: PostgresConnectionManager::new(config, tls),
+            mode,
         }
     }
 }
@@ -42,7 +44,7 @@ where
     /// Attempts to create a new connection.
     async fn connect(&self) -> Result<Self::Connection, Self::Error> {
         let conn = self.inner.connect().await?;
-        Ok(PgConn::new(conn))
+        Ok(PgConn::new(conn, self.mode.clone()))
     }
 
     /// Determine whether connection is still connected.
@@ -52,6 +54,6 @@ where
 
     /// Determine whether connection is usable.
     fn has_broken(&self, conn: &mut Self::Connection) -> bool {
-        self.inner.has_broken(&mut conn.inner)
+        conn.is_broken() || self.inner.has_broken(&mut conn.inner)
     }
 }
@@ -1,44 +1,205 @@
-use crate::{db::sql, Error, Result};
+use crate::{
+    config::{Config as AppConfig, DbPoolKind},
+    db::sql,
+    Error, Result,
+};
 use async_trait::async_trait;
-use bb8::{CustomizeConnection, Pool, RunError};
+use bb8::{CustomizeConnection, Pool, PooledConnection, RunError};
 use std::error::Error as StdError;
+use std::ops::Deref;
 use std::str::FromStr;
-use tokio_postgres::{config::Config, Error as PgError, NoTls};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard};
+use tokio_postgres::{config::Config, Error as PgError, NoTls, Transaction};
 
 pub mod connection;
-use connection::PgConn;
+use connection::{PgConn, StatementMode};
+mod deadpool_manager;
+use deadpool_manager::DeadpoolManager;
 mod manager;
 use manager::PgConnManager;
+mod mux;
+use mux::MuxPool;
 
-/// Custom postgres connection pool.
-pub type PgPool = Pool<PgConnManager<NoTls>>;
+/// A pool of custom postgres connections, backed by one of the supported implementations.
+#[derive(Clone)]
+pub enum PgPool {
+    Bb8(Pool<PgConnManager<NoTls>>),
+    Deadpool(deadpool::managed::Pool<DeadpoolManager>),
+    Mux(Arc<MuxPool>),
+}
+
+impl PgPool {
+    /// Check out a connection for statements that don't need a transaction.
+    pub async fn get(&self) -> Result<PgPooledConn> {
+        match self {
+            Self::Bb8(pool) => Ok(PgPooledConn::Bb8(pool.get_owned().await?)),
+            Self::Deadpool(pool) => Ok(PgPooledConn::Deadpool(pool.get().await?)),
+            Self::Mux(pool) => Ok(PgPooledConn::Shared(pool.get().await?)),
+        }
+    }
+
+    /// Check out a connection that no other task uses until it is returned.
+    /// Required for transactions.
+    ///
+    /// With the multiplexed pool, the calling task must not hold any other connection
+    /// from the same pool while it waits, or while it holds the exclusive one: the
+    /// single connection is behind a lock that isn't re-entrant, so that deadlocks until
+    /// the pool timeout.
+    pub async fn get_exclusive(&self) -> Result<PgPooledConn> {
+        match self {
+            Self::Mux(pool) => Ok(PgPooledConn::Exclusive(pool.get_exclusive().await?)),
+            _ => self.get().await,
+        }
+    }
+}
+
+/// A connection checked out from the custom pool.
+pub enum PgPooledConn {
+    Bb8(PooledConnection<'static, PgConnManager<NoTls>>),
+    Deadpool(deadpool::managed::Object<DeadpoolManager>),
+    Shared(OwnedRwLockReadGuard<PgConn>),
+    Exclusive(OwnedRwLockWriteGuard<PgConn>),
+}
+
+impl PgPooledConn {
+    /// Start a transaction on an exclusive connection.
+    pub async fn transaction(&mut self) -> Result<Transaction<'_>> {
+        let conn: &mut PgConn = match self {
+            Self::Bb8(conn) => conn,
+            Self::Deadpool(conn) => conn,
+            Self::Exclusive(conn) => conn,
+            Self::Shared(_) => {
+                return Err(Error::internal("transaction on a shared connection".into()))
+            }
+        };
+        Ok(conn.inner.transaction().await?)
+    }
+
+    /// Discard the connection instead of returning it to the pool for reuse.
+    /// Closing it makes the server roll back any transaction left open on it.
+    pub fn discard(self) {
+        self.mark_broken();
+        // bb8 checks for broken connections on return, and the multiplexed pool reconnects
+        // on the next checkout; deadpool would only check on the next checkout, so detach now
+        if let Self::Deadpool(conn) = self {
+            drop(deadpool::managed::Object::take(conn));
+        }
+    }
+}
+
+/// Deref pointer calls to the pooled connection.
+impl Deref for PgPooledConn {
+    type Target = PgConn;
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Bb8(conn) => conn,
+            Self::Deadpool(conn) => conn,
+            Self::Shared(conn) => conn,
+            Self::Exclusive(conn) => conn,
+        }
+    }
+}
 
 /// Used to construct a custom postgres connection pool.
 pub struct PgPoolBuilder {}
 
 impl PgPoolBuilder {
-    /// Create a pool of custom connections with pre-cached prepared statements.
-    pub async fn build(db_url: &str, max_size: u32) -> Result<PgPool> {
+    /// Create a pool of custom connections to the primary with pre-cached prepared statements.
+    pub async fn build(config: &AppConfig) -> Result<PgPool> {
+        Self::build_pool(&config.db_url, config, false).await
+    }
+
+    /// Create a pool for the configured replica, if any, that never connects until first used,
+    /// so an unreachable replica can't fail startup; reads go to the primary meanwhile.
+    pub async fn build_replica(config: &AppConfig) -> Result<Option<PgPool>> {
+        match config.db_replica_url.as_ref() {
+            Some(db_url) => Ok(Some(Self::build_pool(db_url, config, true).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn build_pool(db_url: &str, config: &AppConfig, lazy: bool) -> Result<PgPool> {
         let cfg = Config::from_str(db_url)?;
-        let mgr = PgConnManager::new(cfg, NoTls);
-        Pool::builder()
-            .connection_customizer(Box::new(PgConnCustomizer))
-            .max_size(max_size)
-            .build(mgr)
-            .await
-            .map_err(Error::from)
+        let mode = if config.db_pgbouncer {
+            StatementMode::unnamed(&config.db_schema)
+        } else {
+            StatementMode::Named
+        };
+        let mgr = PgConnManager::new(cfg, NoTls, mode);
+        let prepare_eager = config.db_prepare_eager;
+        let timeout = Duration::from_secs(config.db_pool_timeout_secs);
+
+        match config.db_pool {
+            DbPoolKind::Bb8 => {
+                let builder = Pool::builder()
+                    .connection_customizer(Box::new(PgConnCustomizer { prepare_eager }))
+                    .max_size(config.db_max_pool_size)
+                    .connection_timeout(timeout);
+                if lazy {
+                    return Ok(PgPool::Bb8(builder.build_unchecked(mgr)));
+                }
+                builder
+                    .build(mgr)
+                    .await
+                    .map(PgPool::Bb8)
+                    .map_err(Error::from)
+            }
+            DbPoolKind::Deadpool => {
+                let mgr = DeadpoolManager::new(mgr, prepare_eager);
+                deadpool::managed::Pool::builder(mgr)
+                    .max_size(config.db_max_pool_size as usize)
+                    .wait_timeout(Some(timeout))
+                    .create_timeout(Some(timeout))
+                    .runtime(deadpool::Runtime::Tokio1)
+                    .build()
+                    .map(PgPool::Deadpool)
+                    .map_err(|err| Error::internal(err.to_string()))
+            }
+            DbPoolKind::Mux => Ok(PgPool::Mux(Arc::new(MuxPool::new(
+                mgr,
+                prepare_eager,
+                timeout,
+            )))),
+        }
     }
 }
 
+/// Set up a new connection before it is handed out by any pool.
+async fn init_conn(conn: &PgConn, prepare_eager: bool) -> Result<(), PgError> {
+    // Session state doesn't survive transaction pooling; sql is schema qualified instead
+    if !conn.is_named() {
+        return Ok(());
+    }
+
+    // Set search patch for schema support
+    conn.execute(sql::SET_SEARCH_PATH, &[]).await?;
+
+    // Prepare all known statements up front so no request pays for it
+    if prepare_eager {
+        let start = Instant::now();
+        conn.prepare_all().await?;
+        tracing::debug!(
+            "prepared {} statements in {:?}",
+            sql::Stmt::ALL.len(),
+            start.elapsed()
+        );
+    }
+
+    Ok(())
+}
+
+/// Sets up new connections before they are added to the bb8 pool.
 #[derive(Debug)]
-struct PgConnCustomizer;
+struct PgConnCustomizer {
+    prepare_eager: bool,
+}
 
 #[async_trait]
 impl CustomizeConnection<PgConn, PgError> for PgConnCustomizer {
     async fn on_acquire(&self, conn: &mut PgConn) -> Result<(), PgError> {
-        // Set search patch for schema support
-        conn.execute(sql::SET_SEARCH_PATH, &[]).await?;
-        Ok(())
+        init_conn(conn, self.prepare_eager).await
     }
 }
 
@@ -58,3 +219,10 @@ impl<E: StdError> From<RunError<E>> for Error {
         }
     }
 }
+
+/// Map deadpool errors to project errors.
+impl From<deadpool::managed::PoolError<PgError>> for Error {
+    fn from(err: deadpool::managed::PoolError<PgError>) -> Self {
+        Error::internal(err.to_string())
+    }
+}
@@ -0,0 +1,79 @@
+use super::{init_conn, manager::PgConnManager, PgConn};
+use crate::{Error, Result};
+use bb8::ManageConnection;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+use tokio_postgres::NoTls;
+
+/// A single connection shared by all tasks. Concurrent queries are pipelined
+/// over the one client; transactions lock it for exclusive use.
+///
+/// The lock is not re-entrant: a task holding a shared guard that asks for the exclusive
+/// one, or the reverse, waits on itself until the timeout.
+pub struct MuxPool {
+    manager: PgConnManager<NoTls>,
+    prepare_eager: bool,
+    timeout: Duration,
+    slot: Mutex<Option<Arc<RwLock<PgConn>>>>,
+}
+
+impl MuxPool {
+    /// Create a new multiplexed pool; connects on first use.
+    /// Checkouts wait at most `timeout` for the lock, like the other pools' wait timeouts.
+    pub fn new(manager: PgConnManager<NoTls>, prepare_eager: bool, timeout: Duration) -> Self {
+        Self {
+            manager,
+            prepare_eager,
+            timeout,
+            slot: Mutex::new(None),
+        }
+    }
+
+    /// Get shared access to the connection.
+    pub async fn get(&self) -> Result<OwnedRwLockReadGuard<PgConn>> {
+        loop {
+            // The connection may have been marked broken while we waited for the lock
+            let conn = self.wait(self.conn().await?.read_owned()).await?;
+            if !conn.is_broken() {
+                return Ok(conn);
+            }
+        }
+    }
+
+    /// Get exclusive access to the connection.
+    pub async fn get_exclusive(&self) -> Result<OwnedRwLockWriteGuard<PgConn>> {
+        loop {
+            let conn = self.wait(self.conn().await?.write_owned()).await?;
+            if !conn.is_broken() {
+                return Ok(conn);
+            }
+        }
+    }
+
+    /// Wait for a lock on the connection, up to the checkout timeout.
+    async fn wait<T>(&self, lock: impl Future<Output = T>) -> Result<T> {
+        tokio::time::timeout(self.timeout, lock)
+            .await
+            .map_err(|_| Error::internal("connection timed out".into()))
+    }
+
+    /// Get the shared connection, reconnecting if it has been closed or marked broken.
+    async fn conn(&self) -> Result<Arc<RwLock<PgConn>>> {
+        let mut slot = self.slot.lock().await;
+        if let Some(conn) = slot.as_ref() {
+            // A locked connection is in use, so assume it is still open
+            let broken = conn.try_read().map(|c| c.is_broken()).unwrap_or(false);
+            if !broken {
+                return Ok(Arc::clone(conn));
+            }
+            tracing::warn!("multiplexed connection closed or broken, reconnecting");
+        }
+        let conn = self.manager.connect().await?;
+        init_conn(&conn, self.prepare_eager).await?;
+        let conn = Arc::new(RwLock::new(conn));
+        *slot = Some(Arc::clone(&conn));
+        Ok(conn)
+    }
+}
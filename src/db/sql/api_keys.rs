@@ -0,0 +1,10 @@
+pub const FETCH_BY_HASH: &str =
+    "select id, scope from api_keys where key_hash = $1 and revoked_at is null";
+
+/// Key administration, run from the command line.
+pub const INSERT: &str = r#"insert into api_keys (name, key_hash, scope) values ($1, $2, $3)
+returning id, name, scope, created_at::text, revoked_at::text"#;
+pub const REVOKE: &str =
+    "update api_keys set revoked_at = now() where id = $1 and revoked_at is null";
+pub const SELECT_ALL: &str =
+    "select id, name, scope, created_at::text, revoked_at::text from api_keys order by id";
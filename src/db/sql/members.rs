@@ -0,0 +1,9 @@
+pub const FETCH_ROLE: &str = "select role from story_members where story_id = $1 and member = $2";
+pub const SELECT_BY_STORY: &str =
+    "select member, role from story_members where story_id = $1 order by created_at, member";
+pub const INSERT: &str = r#"insert into story_members (story_id, member, role) values ($1, $2, $3)
+on conflict (story_id, member) do nothing
+returning member"#;
+pub const UPDATE_ROLE: &str =
+    "update story_members set role = $3 where story_id = $1 and member = $2 returning member";
+pub const DELETE: &str = "delete from story_members where story_id = $1 and member = $2";
@@ -1,8 +1,358 @@
+use crate::config::Config;
+use std::borrow::Cow;
+use tokio_postgres::types::Type;
+
+/// Queries for the "api_keys" table
+pub mod api_keys;
+
+/// Queries for the "story_members" table
+pub mod members;
+
 /// Queries for the "stories" table
 pub mod stories;
 
 /// Queries for the "tasks" table
 pub mod tasks;
 
+/// Queries for the "users" and "sessions" tables
+pub mod users;
+
 /// Supports tables existing in multiple schemas.
 pub const SET_SEARCH_PATH: &str = "set search_path to public,bb8_todos";
+
+/// Replication lag in milliseconds; zero when caught up or when run on a primary.
+pub const REPLICA_LAG: &str = r#"select (case
+    when pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() then 0
+    else coalesce(extract(epoch from now() - pg_last_xact_replay_timestamp()) * 1000, 0)
+end)::int8"#;
+
+/// Tables that are schema qualified when search_path can't be used.
+const TABLES: &[&str] = &[
+    "stories",
+    "tasks",
+    "api_keys",
+    "users",
+    "sessions",
+    "story_members",
+];
+
+/// Typed keys for the statements executed by the repo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stmt {
+    FetchStory,
+    LockStory,
+    SelectStories,
+    SelectOwnedStories,
+    SelectSharedStories,
+    InsertStory,
+    InsertStoryBatch,
+    DeleteStory,
+    UpdateStory,
+    FetchTask,
+    SelectTasks,
+    InsertTask,
+    InsertTasks,
+    InsertTaskBatch,
+    DeleteTask,
+    DeleteTasksByStory,
+    DeleteTasksByStatus,
+    UpdateTask,
+    UpdateTaskStatuses,
+    UpdateStoryTaskStatuses,
+    FetchApiKey,
+    InsertUser,
+    FetchUser,
+    InsertSession,
+    FetchSession,
+    DeleteSession,
+    DeleteExpiredSessions,
+    RefreshSession,
+    FetchMemberRole,
+    SelectMembers,
+    InsertMember,
+    UpdateMemberRole,
+    DeleteMember,
+}
+
+impl Stmt {
+    /// Every known statement; prepared eagerly when a connection is created.
+    pub const ALL: &'static [Stmt] = &[
+        Stmt::FetchStory,
+        Stmt::LockStory,
+        Stmt::SelectStories,
+        Stmt::SelectOwnedStories,
+        Stmt::SelectSharedStories,
+        Stmt::InsertStory,
+        Stmt::InsertStoryBatch,
+        Stmt::DeleteStory,
+        Stmt::UpdateStory,
+        Stmt::FetchTask,
+        Stmt::SelectTasks,
+        Stmt::InsertTask,
+        Stmt::InsertTasks,
+        Stmt::InsertTaskBatch,
+        Stmt::DeleteTask,
+        Stmt::DeleteTasksByStory,
+        Stmt::DeleteTasksByStatus,
+        Stmt::UpdateTask,
+        Stmt::UpdateTaskStatuses,
+        Stmt::UpdateStoryTaskStatuses,
+        Stmt::FetchApiKey,
+        Stmt::InsertUser,
+        Stmt::FetchUser,
+        Stmt::InsertSession,
+        Stmt::FetchSession,
+        Stmt::DeleteSession,
+        Stmt::DeleteExpiredSessions,
+        Stmt::RefreshSession,
+        Stmt::FetchMemberRole,
+        Stmt::SelectMembers,
+        Stmt::InsertMember,
+        Stmt::UpdateMemberRole,
+        Stmt::DeleteMember,
+    ];
+
+    /// The sql text for a statement.
+    pub fn sql(self) -> &'static str {
+        match self {
+            Stmt::FetchStory => stories::FETCH,
+            Stmt::LockStory => stories::LOCK_FOR_SHARE,
+            Stmt::SelectStories => stories::SELECT,
+            Stmt::SelectOwnedStories => stories::SELECT_OWNED,
+            Stmt::SelectSharedStories => stories::SELECT_SHARED,
+            Stmt::InsertStory => stories::INSERT,
+            Stmt::InsertStoryBatch => stories::INSERT_BATCH,
+            Stmt::DeleteStory => stories::DELETE,
+            Stmt::UpdateStory => stories::UPDATE,
+            Stmt::FetchTask => tasks::FETCH,
+            Stmt::SelectTasks => tasks::SELECT,
+            Stmt::InsertTask => tasks::INSERT,
+            Stmt::InsertTasks => tasks::INSERT_MANY,
+            Stmt::InsertTaskBatch => tasks::INSERT_BATCH,
+            Stmt::DeleteTask => tasks::DELETE,
+            Stmt::DeleteTasksByStory => tasks::DELETE_BY_STORY,
+            Stmt::DeleteTasksByStatus => tasks::DELETE_BY_STORY_STATUS,
+            Stmt::UpdateTask => tasks::UPDATE,
+            Stmt::UpdateTaskStatuses => tasks::UPDATE_STATUS_BY_IDS,
+            Stmt::UpdateStoryTaskStatuses => tasks::UPDATE_STATUS_BY_STORY,
+            Stmt::FetchApiKey => api_keys::FETCH_BY_HASH,
+            Stmt::InsertUser => users::INSERT,
+            Stmt::FetchUser => users::FETCH_BY_USERNAME,
+            Stmt::InsertSession => users::INSERT_SESSION,
+            Stmt::FetchSession => users::FETCH_SESSION,
+            Stmt::DeleteSession => users::DELETE_SESSION,
+            Stmt::DeleteExpiredSessions => users::DELETE_EXPIRED_SESSIONS,
+            Stmt::RefreshSession => users::REFRESH_SESSION,
+            Stmt::FetchMemberRole => members::FETCH_ROLE,
+            Stmt::SelectMembers => members::SELECT_BY_STORY,
+            Stmt::InsertMember => members::INSERT,
+            Stmt::UpdateMemberRole => members::UPDATE_ROLE,
+            Stmt::DeleteMember => members::DELETE,
+        }
+    }
+
+    /// The parameter types for a statement, needed when executing unnamed statements.
+    pub fn param_types(self) -> &'static [Type] {
+        match self {
+            Stmt::FetchStory => &[Type::INT4],
+            Stmt::LockStory => &[Type::INT4, Type::TEXT],
+            Stmt::SelectStories | Stmt::DeleteStory => &[Type::INT4],
+            Stmt::FetchTask | Stmt::DeleteTask | Stmt::DeleteTasksByStory => &[Type::INT4],
+            Stmt::SelectOwnedStories | Stmt::SelectSharedStories => &[Type::INT4, Type::TEXT],
+            Stmt::SelectMembers => &[Type::INT4],
+            Stmt::FetchMemberRole | Stmt::DeleteMember => &[Type::INT4, Type::TEXT],
+            Stmt::InsertMember | Stmt::UpdateMemberRole => &[Type::INT4, Type::TEXT, Type::TEXT],
+            Stmt::InsertStory => &[Type::TEXT, Type::TEXT],
+            Stmt::InsertStoryBatch => &[Type::TEXT_ARRAY, Type::TEXT_ARRAY],
+            Stmt::UpdateStory => &[Type::TEXT, Type::INT4, Type::TEXT],
+            Stmt::SelectTasks => &[Type::INT4, Type::INT4],
+            Stmt::InsertTask => &[Type::INT4, Type::TEXT, Type::TEXT, Type::TEXT],
+            Stmt::InsertTasks => &[Type::INT4, Type::TEXT_ARRAY, Type::TEXT],
+            Stmt::InsertTaskBatch => &[
+                Type::INT4_ARRAY,
+                Type::TEXT_ARRAY,
+                Type::TEXT,
+                Type::TEXT_ARRAY,
+            ],
+            Stmt::UpdateTask => &[Type::TEXT, Type::TEXT, Type::INT4],
+            Stmt::DeleteTasksByStatus => &[Type::INT4, Type::TEXT, Type::TEXT],
+            Stmt::UpdateTaskStatuses => &[Type::TEXT, Type::INT4_ARRAY, Type::TEXT],
+            Stmt::UpdateStoryTaskStatuses => &[Type::TEXT, Type::INT4, Type::TEXT, Type::TEXT],
+            Stmt::FetchApiKey | Stmt::FetchUser => &[Type::TEXT],
+            Stmt::FetchSession | Stmt::DeleteSession => &[Type::TEXT],
+            Stmt::DeleteExpiredSessions => &[Type::INT4],
+            Stmt::InsertUser => &[Type::TEXT, Type::TEXT],
+            Stmt::InsertSession => &[Type::TEXT, Type::INT4, Type::FLOAT8],
+            Stmt::RefreshSession => &[Type::TEXT, Type::TEXT, Type::FLOAT8],
+        }
+    }
+}
+
+/// Sql for connections made with a config: schema qualified when search_path isn't set on
+/// them, as with pgbouncer, and unchanged otherwise.
+pub fn for_config<'a>(sql: &'a str, config: &Config) -> Cow<'a, str> {
+    if config.db_pgbouncer {
+        Cow::Owned(qualify(sql, &config.db_schema))
+    } else {
+        Cow::Borrowed(sql)
+    }
+}
+
+/// Prefix every known table name in sql with a schema, including quoted names and row types
+/// in casts. Identifiers that are already qualified and string literals are left as-is.
+pub fn qualify(sql: &str, schema: &str) -> String {
+    let mut out = String::with_capacity(sql.len() + 32);
+    let mut word = String::new();
+    let mut in_literal = false;
+    let mut in_quoted = false;
+    let mut prev = ' ';
+
+    for c in sql.chars().chain(std::iter::once(' ')) {
+        if in_quoted {
+            if c == '"' {
+                in_quoted = false;
+                if prev != '.' && TABLES.contains(&word.as_str()) {
+                    out.push_str(schema);
+                    out.push('.');
+                }
+                out.push('"');
+                out.push_str(&word);
+                out.push('"');
+                word.clear();
+                prev = c;
+            } else {
+                word.push(c);
+            }
+            continue;
+        }
+        if !in_literal && (c.is_ascii_alphanumeric() || c == '_') {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            if prev != '.' && TABLES.contains(&word.as_str()) {
+                out.push_str(schema);
+                out.push('.');
+            }
+            out.push_str(&word);
+            word.clear();
+        }
+        if !in_literal && c == '"' {
+            in_quoted = true;
+            continue;
+        }
+        if c == '\'' {
+            in_literal = !in_literal;
+        }
+        out.push(c);
+        prev = c;
+    }
+
+    out.pop();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    const SCHEMA: &str = "zz_schema";
+
+    /// Words outside string literals, with the character before each.
+    fn words(sql: &str) -> Vec<(char, String)> {
+        let mut words = Vec::new();
+        let mut word = String::new();
+        let mut in_literal = false;
+        let mut prev = ' ';
+        for c in sql.chars().chain(std::iter::once(' ')) {
+            if !in_literal && (c.is_ascii_alphanumeric() || c == '_' || c == '$') {
+                word.push(c);
+                continue;
+            }
+            if !word.is_empty() {
+                words.push((prev, std::mem::take(&mut word)));
+            }
+            if c == '\'' {
+                in_literal = !in_literal;
+            }
+            prev = c;
+        }
+        words
+    }
+
+    #[test]
+    fn qualifies_table_names() {
+        assert_eq!(
+            qualify("select id from stories where id = $1", SCHEMA),
+            "select id from zz_schema.stories where id = $1"
+        );
+        assert_eq!(
+            qualify("delete from tasks using story_members m", SCHEMA),
+            "delete from zz_schema.tasks using zz_schema.story_members m"
+        );
+    }
+
+    #[test]
+    fn leaves_qualified_names_and_other_words() {
+        let sql = "select t.tasks, stories_owner_id_index from public.stories join x.tasks t";
+        assert_eq!(qualify(sql, SCHEMA), sql);
+    }
+
+    #[test]
+    fn leaves_string_literals() {
+        let sql = "select 'stories', 'it''s tasks', pg_get_serial_sequence('users', 'id')";
+        assert_eq!(qualify(sql, SCHEMA), sql);
+    }
+
+    #[test]
+    fn qualifies_quoted_identifiers() {
+        assert_eq!(
+            qualify(
+                r#"select "id" from "stories" join "Tasks" using (id)"#,
+                SCHEMA
+            ),
+            r#"select "id" from zz_schema."stories" join "Tasks" using (id)"#
+        );
+        let sql = r#"select 1 from public."stories", "has 'quote""#;
+        assert_eq!(qualify(sql, SCHEMA), sql);
+    }
+
+    #[test]
+    fn qualifies_row_types_in_casts() {
+        assert_eq!(
+            qualify(
+                "select pg_typeof(null::stories)::text, $1::text[], 'x'::tasks",
+                SCHEMA
+            ),
+            "select pg_typeof(null::zz_schema.stories)::text, $1::text[], 'x'::zz_schema.tasks"
+        );
+    }
+
+    #[test]
+    fn qualifies_every_statement() {
+        for stmt in Stmt::ALL {
+            let sql = stmt.sql();
+            let qualified = qualify(sql, SCHEMA);
+            assert_eq!(qualified.replace("zz_schema.", ""), sql, "{:?}", stmt);
+            assert_eq!(qualify(&qualified, SCHEMA), qualified, "{:?}", stmt);
+            for (prev, word) in words(&qualified) {
+                if TABLES.contains(&word.as_str()) {
+                    assert_eq!(prev, '.', "{:?}: unqualified {}", stmt, word);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn param_types_match_placeholders() {
+        for stmt in Stmt::ALL {
+            let placeholders: BTreeSet<usize> = words(stmt.sql())
+                .into_iter()
+                .filter_map(|(_, w)| w.strip_prefix('$').and_then(|n| n.parse().ok()))
+                .collect();
+            let expected: BTreeSet<usize> = (1..=stmt.param_types().len()).collect();
+            assert_eq!(placeholders, expected, "{:?}", stmt);
+        }
+    }
+}
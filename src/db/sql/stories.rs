@@ -1,26 +1,112 @@
-pub const FETCH: &str = "select id, name from stories where id = $1";
-pub const INSERT: &str = "insert into stories (name) values ($1) returning id";
+pub const FETCH: &str = "select id, name, owner from stories where id = $1";
+/// The editor filters here and below are `null` for admins, otherwise the caller's subject,
+/// which must own the story or be one of its editors. Checked in the statement, so access
+/// can't change between the check and the write.
+pub const LOCK_FOR_SHARE: &str = r#"select id from stories
+where id = $1
+and ($2::text is null or owner = $2 or exists (
+    select 1 from story_members where story_id = $1 and member = $2 and role = 'editor'
+))
+for share"#;
+pub const INSERT: &str = "insert into stories (name, owner) values ($1, $2) returning id";
+/// Ids are drawn up front so each can be returned with the position of its input row;
+/// `returning` can't see the input, and doesn't promise any row order.
+/// The row type cast names the table, so it is schema qualified like any other reference.
+pub const INSERT_BATCH: &str = r#"with input as (
+    select nextval(pg_get_serial_sequence(pg_typeof(null::stories)::text, 'id'))::int4 as id,
+        name, owner, ord
+    from unnest($1::text[], $2::text[]) with ordinality as t(name, owner, ord)
+), inserted as (
+    insert into stories (id, name, owner) overriding system value
+    select id, name, owner from input
+    returning id
+)
+select input.id, input.ord from input join inserted using (id)"#;
 pub const DELETE: &str = "delete from stories where id = $1";
-pub const UPDATE: &str = "update stories set name = $1 where id = $2";
+pub const UPDATE: &str = r#"update stories set name = $1
+where id = $2
+and ($3::text is null or owner = $3 or exists (
+    select 1 from story_members where story_id = $2 and member = $3 and role = 'editor'
+))
+returning owner"#;
 
 pub const SELECT: &str = r#"with cursor as (
     select id from stories
     where id = $1
 ), previous_page as (
-    select id, name from stories
+    select id, name, owner from stories
     where id < (select id from cursor)
     order by id desc limit 100
 ), current_next_page as (
-    select id, name from stories
+    select id, name, owner from stories
     where id >= (select id from cursor)
     order by id limit 101
 ) (
-    select id, name, 'prev' as label from previous_page
+    select id, name, owner, 'prev' as label from previous_page
     order by id limit 1
 ) union all (
-    select id, name, 'current' as label from current_next_page
+    select id, name, owner, 'current' as label from current_next_page
     order by id limit 100
 ) union all (
-    select id, name, 'next' as label from current_next_page
+    select id, name, owner, 'next' as label from current_next_page
     order by id limit 1 offset 100
 )"#;
+
+/// A page of the stories owned by $2, starting at or after the story with id $1.
+pub const SELECT_OWNED: &str = r#"with previous_page as (
+    select id, name, owner from stories
+    where owner = $2 and id < $1
+    order by id desc limit 100
+), current_next_page as (
+    select id, name, owner from stories
+    where owner = $2 and id >= $1
+    order by id limit 101
+) (
+    select id, name, owner, 'prev' as label from previous_page
+    order by id limit 1
+) union all (
+    select id, name, owner, 'current' as label from current_next_page
+    order by id limit 100
+) union all (
+    select id, name, owner, 'next' as label from current_next_page
+    order by id limit 1 offset 100
+)"#;
+
+/// A page of the stories shared with member $2, starting at or after the story with id $1.
+pub const SELECT_SHARED: &str = r#"with shared as (
+    select s.id, s.name, s.owner, m.role from stories s
+    join story_members m on m.story_id = s.id
+    where m.member = $2
+), previous_page as (
+    select id, name, owner, role from shared
+    where id < $1
+    order by id desc limit 100
+), current_next_page as (
+    select id, name, owner, role from shared
+    where id >= $1
+    order by id limit 101
+) (
+    select id, name, owner, 'prev' as label, role from previous_page
+    order by id limit 1
+) union all (
+    select id, name, owner, 'current' as label, role from current_next_page
+    order by id limit 100
+) union all (
+    select id, name, owner, 'next' as label, role from current_next_page
+    order by id limit 1 offset 100
+)"#;
+
+/// Bulk loading support for seeding.
+pub const LOCK: &str = "lock table stories in exclusive mode";
+pub const MAX_ID: &str = "select coalesce(max(id), 0) from stories";
+pub const SELECT_IDS_AFTER: &str = "select id from stories where id > $1 order by id";
+pub const COPY_IN: &str = "copy stories (name) from stdin binary";
+pub const COPY_IN_OWNED: &str = "copy stories (name, owner) from stdin binary";
+
+/// Give a batch of ownerless stories an owner, for backfilling after the owner migration.
+pub const ASSIGN_OWNER: &str = r#"update stories set owner = $1
+where id in (
+    select id from stories where owner is null
+    order by id limit $2
+    for update skip locked
+)"#;
@@ -1,9 +1,85 @@
 pub const FETCH: &str = "select id, story_id, name, status from tasks where id = $1";
 pub const DELETE: &str = "delete from tasks where id = $1";
 pub const DELETE_BY_STORY: &str = "delete from tasks where story_id = $1";
+pub const UPDATE_STATUS_BY_IDS: &str = r#"update tasks set status = $1
+where id = any($2)
+and ($3::text is null or story_id in (
+    select id from stories where owner = $3
+    union all
+    select story_id from story_members where member = $3 and role = 'editor'
+))"#;
+/// Returns whether the caller may edit the story, which also means it exists, and the count.
+pub const UPDATE_STATUS_BY_STORY: &str = r#"with story as (
+    select id from stories
+    where id = $2
+    and ($4::text is null or owner = $4 or exists (
+        select 1 from story_members where story_id = $2 and member = $4 and role = 'editor'
+    ))
+), updated as (
+    update tasks set status = $1
+    where story_id in (select id from story) and ($3::text is null or status = $3)
+    returning 1
+)
+select exists (select 1 from story), (select count(*) from updated)"#;
+/// Returns whether the caller may edit the story, like `UPDATE_STATUS_BY_STORY`.
+pub const DELETE_BY_STORY_STATUS: &str = r#"with story as (
+    select id from stories
+    where id = $1
+    and ($3::text is null or owner = $3 or exists (
+        select 1 from story_members where story_id = $1 and member = $3 and role = 'editor'
+    ))
+), deleted as (
+    delete from tasks
+    where story_id in (select id from story) and ($2::text is null or status = $2)
+    returning 1
+)
+select exists (select 1 from story), (select count(*) from deleted)"#;
 pub const UPDATE: &str = "update tasks set name = $1, status = $2 where id = $3 returning story_id";
-pub const INSERT: &str =
-    "insert into tasks (story_id, name, status) values ($1, $2, $3) returning id";
+/// Inserts nothing unless the caller may edit the story; see `stories::LOCK_FOR_SHARE`.
+pub const INSERT: &str = r#"insert into tasks (story_id, name, status)
+select id, $2, $3 from stories
+where id = $1
+and ($4::text is null or owner = $4 or exists (
+    select 1 from story_members where story_id = $1 and member = $4 and role = 'editor'
+))
+returning id"#;
+/// Returns each id with the position of its input row, like `stories::INSERT_BATCH`.
+pub const INSERT_MANY: &str = r#"with input as (
+    select nextval(pg_get_serial_sequence(pg_typeof(null::tasks)::text, 'id'))::int4 as id,
+        name, ord
+    from unnest($2::text[]) with ordinality as t(name, ord)
+), inserted as (
+    insert into tasks (id, story_id, name, status) overriding system value
+    select id, $1, name, $3 from input
+    returning id
+)
+select input.id, input.ord from input join inserted using (id)
+order by input.ord"#;
+/// Tasks for stories that don't exist, or that their caller can't edit, are skipped rather
+/// than failing the whole batch; each row carries its caller's editor filter.
+/// Joined stories are locked so a concurrent delete waits for the insert, instead of the
+/// insert failing the whole batch on the foreign key.
+/// Returns each id with the position of its input row, like `stories::INSERT_BATCH`.
+pub const INSERT_BATCH: &str = r#"with input as (
+    select nextval(pg_get_serial_sequence(pg_typeof(null::tasks)::text, 'id'))::int4 as id,
+        t.story_id, t.name, t.ord
+    from unnest($1::int4[], $2::text[], $4::text[])
+        with ordinality as t(story_id, name, editor, ord)
+    join stories s on s.id = t.story_id
+    where t.editor is null or s.owner = t.editor or exists (
+        select 1 from story_members m
+        where m.story_id = s.id and m.member = t.editor and m.role = 'editor'
+    )
+    for key share of s
+), inserted as (
+    insert into tasks (id, story_id, name, status) overriding system value
+    select id, story_id, name, $3 from input
+    returning id
+)
+select input.id, input.ord from input join inserted using (id)"#;
 pub const SELECT: &str = r#"
 select id, story_id, name, status from tasks where story_id = $1 and id >= $2 order by id limit 10
 "#;
+
+/// Bulk loading support for seeding.
+pub const COPY_IN: &str = "copy tasks (story_id, name, status) from stdin binary";
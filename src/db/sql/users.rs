@@ -0,0 +1,20 @@
+pub const INSERT: &str = r#"insert into users (username, password_hash) values ($1, $2)
+on conflict (username) do nothing
+returning id, username"#;
+pub const FETCH_BY_USERNAME: &str =
+    "select id, username, password_hash from users where username = $1";
+
+pub const INSERT_SESSION: &str = r#"insert into sessions (token_hash, user_id, expires_at)
+values ($1, $2, now() + make_interval(secs => $3))"#;
+pub const FETCH_SESSION: &str = r#"select u.id, u.scope from sessions s
+join users u on u.id = s.user_id
+where s.token_hash = $1 and s.expires_at > now()"#;
+pub const DELETE_SESSION: &str = "delete from sessions where token_hash = $1";
+pub const DELETE_EXPIRED_SESSIONS: &str =
+    "delete from sessions where user_id = $1 and expires_at <= now()";
+
+/// Swap a live session for a new token, extending its expiry.
+pub const REFRESH_SESSION: &str = r#"update sessions
+set token_hash = $2, expires_at = now() + make_interval(secs => $3)
+where token_hash = $1 and expires_at > now()
+returning user_id"#;
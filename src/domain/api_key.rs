@@ -0,0 +1,49 @@
+use crate::Error;
+use serde::Serialize;
+use std::str::FromStr;
+
+// Scope strings
+const READ: &str = "read";
+const WRITE: &str = "write";
+const ADMIN: &str = "admin";
+
+/// What an api key may do. Each scope includes the ones before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    Read,
+    Write,
+    Admin,
+}
+
+impl FromStr for Scope {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s.trim().to_lowercase().as_str() {
+            READ => Ok(Self::Read),
+            WRITE => Ok(Self::Write),
+            ADMIN => Ok(Self::Admin),
+            _ => Err(Error::invalid_args("invalid scope")),
+        }
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            Self::Read => f.write_str(READ),
+            Self::Write => f.write_str(WRITE),
+            Self::Admin => f.write_str(ADMIN),
+        }
+    }
+}
+
+/// An api key as listed for admins; the key itself is only stored hashed.
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    pub scope: Scope,
+    pub created_at: String,
+    pub revoked_at: Option<String>,
+}
@@ -0,0 +1,81 @@
+use crate::{domain::Story, Error};
+use serde::Serialize;
+use std::str::FromStr;
+
+// Role strings
+const VIEWER: &str = "viewer";
+const EDITOR: &str = "editor";
+const OWNER: &str = "owner";
+
+/// A caller's role on a story. Each role includes the ones before it.
+/// Stories are shared with viewers and editors; only owners and admins hold the owner role.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    /// Check that a role on a story allows an action needing `needed`.
+    /// Callers without any role get not found, so stories they can't see stay hidden.
+    pub fn require(role: Option<Role>, needed: Role, story_id: i32) -> Result<(), Error> {
+        match role {
+            None => Err(Error::not_found(format!("story not found: {}", story_id))),
+            Some(role) if role < needed => {
+                Err(Error::forbidden(format!("requires {} role", needed)))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s.trim().to_lowercase().as_str() {
+            VIEWER => Ok(Self::Viewer),
+            EDITOR => Ok(Self::Editor),
+            OWNER => Ok(Self::Owner),
+            _ => Err(Error::invalid_args("invalid role")),
+        }
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            Self::Viewer => f.write_str(VIEWER),
+            Self::Editor => f.write_str(EDITOR),
+            Self::Owner => f.write_str(OWNER),
+        }
+    }
+}
+
+/// A collaborator a story is shared with, by the subject they authenticate as.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Member {
+    pub story_id: i32,
+    pub member: String,
+    pub role: Role,
+}
+
+impl Member {
+    /// Create a new member
+    pub fn new(story_id: i32, member: String, role: Role) -> Self {
+        Self {
+            story_id,
+            member,
+            role,
+        }
+    }
+}
+
+/// A story shared with the caller, with the role they were given.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SharedStory {
+    #[serde(flatten)]
+    pub story: Story,
+    pub role: Role,
+}
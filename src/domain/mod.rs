@@ -1,9 +1,17 @@
 // Domain modules
+mod api_key;
+mod member;
+mod principal;
 mod status;
 mod story;
 mod task;
+mod user;
 
 // Expose domain types at the top-level module.
+pub use api_key::{ApiKey, Scope};
+pub use member::{Member, Role, SharedStory};
+pub use principal::Principal;
 pub use status::Status;
 pub use story::Story;
 pub use task::Task;
+pub use user::User;
@@ -0,0 +1,38 @@
+use crate::domain::{Scope, Story};
+
+/// The caller a request was authenticated as: an api key, a user, or the subject of a token.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Principal {
+    pub subject: String,
+    pub scope: Scope,
+}
+
+impl Principal {
+    /// The caller when authentication is disabled, allowed to do anything.
+    pub fn anonymous() -> Self {
+        Self {
+            subject: "anonymous".into(),
+            scope: Scope::Admin,
+        }
+    }
+
+    /// Whether the caller may act with the given scope.
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scope >= scope
+    }
+
+    /// Whether the caller sees every story regardless of owner; only admins do.
+    pub fn sees_all(&self) -> bool {
+        self.scope == Scope::Admin
+    }
+
+    /// The owner whose stories the caller is limited to; `None` when they see every story.
+    pub fn owner_filter(&self) -> Option<&str> {
+        (!self.sees_all()).then_some(self.subject.as_str())
+    }
+
+    /// Whether the caller holds every right over a story: its owner, or an admin.
+    pub fn owns(&self, story: &Story) -> bool {
+        self.sees_all() || story.owner.as_deref() == Some(self.subject.as_str())
+    }
+}
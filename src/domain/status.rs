@@ -7,7 +7,7 @@ const COMPLETE: &str = "complete";
 const INCOMPLETE: &str = "incomplete";
 
 /// Indicates whether a task has been completed.
-#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Status {
     Complete,
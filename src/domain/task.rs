@@ -2,7 +2,7 @@ use super::Status;
 use serde::Serialize;
 
 /// A single action item for a story that must be completed.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct Task {
     pub id: i32,
     pub story_id: i32,
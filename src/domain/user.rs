@@ -0,0 +1,20 @@
+use serde::Serialize;
+
+/// A person who can log in with a password.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct User {
+    pub id: i32,
+    pub username: String,
+}
+
+impl User {
+    /// Create a new user
+    pub fn new(id: i32, username: String) -> Self {
+        Self { id, username }
+    }
+
+    /// The subject of principals authenticated as this user.
+    pub fn subject(id: i32) -> String {
+        format!("user:{}", id)
+    }
+}
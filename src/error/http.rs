@@ -1,6 +1,6 @@
 use super::Error;
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -17,6 +17,10 @@ impl IntoResponse for Error {
     fn into_response(self) -> Response {
         let status = http_status_code(&self);
         let error = http_error_dto(&self);
+        if status == StatusCode::UNAUTHORIZED {
+            let challenge = [(header::WWW_AUTHENTICATE, "Bearer")];
+            return (status, challenge, Json(error)).into_response();
+        }
         (status, Json(error)).into_response()
     }
 }
@@ -38,6 +42,11 @@ fn http_status_code(err: &Error) -> StatusCode {
         Error::NotFound { .. } => StatusCode::NOT_FOUND,
         Error::InvalidArgs { .. } => StatusCode::BAD_REQUEST,
         Error::Internal { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        Error::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        Error::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+        Error::Forbidden { .. } => StatusCode::FORBIDDEN,
+        Error::Conflict { .. } => StatusCode::CONFLICT,
+        Error::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
     }
 }
 
@@ -46,6 +55,11 @@ fn http_error_dto(err: &Error) -> ErrorDto {
     let errors = match err {
         Error::InvalidArgs { messages } => messages.to_owned(),
         Error::NotFound { message } => vec![message.to_owned()],
+        Error::TooLarge { message }
+        | Error::Unauthorized { message }
+        | Error::Forbidden { message }
+        | Error::Conflict { message }
+        | Error::TooManyRequests { message } => vec![message.to_owned()],
         Error::Internal { message } => {
             tracing::error!("internal error: {}", message);
             vec![message.to_owned()]
@@ -2,7 +2,7 @@
 mod http;
 
 /// Project level error type
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Clone, Debug)]
 pub enum Error {
     #[error("invalid arguments")]
     InvalidArgs { messages: Vec<String> },
@@ -10,6 +10,16 @@ pub enum Error {
     Internal { message: String },
     #[error("not found error: {message}")]
     NotFound { message: String },
+    #[error("payload too large: {message}")]
+    TooLarge { message: String },
+    #[error("unauthorized: {message}")]
+    Unauthorized { message: String },
+    #[error("forbidden: {message}")]
+    Forbidden { message: String },
+    #[error("conflict: {message}")]
+    Conflict { message: String },
+    #[error("too many requests: {message}")]
+    TooManyRequests { message: String },
 }
 
 // Error helpers
@@ -22,6 +32,24 @@ impl Error {
         Error::NotFound { message }
     }
 
+    pub fn unauthorized(message: &str) -> Self {
+        Error::Unauthorized {
+            message: message.into(),
+        }
+    }
+
+    pub fn forbidden(message: String) -> Self {
+        Error::Forbidden { message }
+    }
+
+    pub fn conflict(message: String) -> Self {
+        Error::Conflict { message }
+    }
+
+    pub fn too_many_requests(message: String) -> Self {
+        Error::TooManyRequests { message }
+    }
+
     pub fn invalid_args(message: &str) -> Self {
         Error::InvalidArgs {
             messages: vec![message.into()],
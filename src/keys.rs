@@ -0,0 +1,129 @@
+use crate::{
+    config::Config,
+    db::{pool::PgPoolBuilder, sql},
+    domain::{ApiKey, Scope},
+    repo::{generate_key, hash_token},
+    Error, Result,
+};
+use std::str::FromStr;
+use tokio_postgres::Row;
+
+/// Usage for the keys subcommand.
+const USAGE: &str =
+    "usage: keys create --name <name> --scope <read|write|admin> | keys revoke <id> | keys list";
+
+/// Api key administration commands.
+#[derive(Debug)]
+pub enum KeysCommand {
+    Create { name: String, scope: Scope },
+    Revoke { id: i32 },
+    List,
+}
+
+impl KeysCommand {
+    /// Parse a command from the arguments after `keys`.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let (cmd, args) = args.split_first().ok_or(USAGE)?;
+        match cmd.as_str() {
+            "create" => {
+                let (mut name, mut scope) = (None, None);
+                let mut args = args.iter();
+                while let Some(flag) = args.next() {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("{}: missing value", flag))?;
+                    match flag.as_str() {
+                        "--name" => name = Some(value.clone()),
+                        "--scope" => {
+                            let parsed = Scope::from_str(value)
+                                .map_err(|_| format!("{}: invalid value: {}", flag, value))?;
+                            scope = Some(parsed)
+                        }
+                        _ => return Err(format!("unknown flag: {}", flag)),
+                    }
+                }
+                match (name, scope) {
+                    (Some(name), Some(scope)) if !name.trim().is_empty() => Ok(Self::Create {
+                        name: name.trim().to_string(),
+                        scope,
+                    }),
+                    _ => Err(USAGE.into()),
+                }
+            }
+            "revoke" => match args {
+                [id] => Ok(Self::Revoke {
+                    id: id.parse().map_err(|_| format!("invalid id: {}", id))?,
+                }),
+                _ => Err(USAGE.into()),
+            },
+            "list" if args.is_empty() => Ok(Self::List),
+            _ => Err(USAGE.into()),
+        }
+    }
+}
+
+/// Run a key administration command against the primary database.
+/// Created keys are printed once; only their hash is stored.
+pub async fn run(config: &Config, cmd: KeysCommand) -> Result<()> {
+    let pool = PgPoolBuilder::build(config).await?;
+    let conn = pool.get().await?;
+
+    match cmd {
+        KeysCommand::Create { name, scope } => {
+            let key = generate_key();
+            let params: [&(dyn tokio_postgres::types::ToSql + Sync); 3] =
+                [&name, &hash_token(&key), &scope.to_string()];
+            let row = conn
+                .inner
+                .query_one(&*sql::for_config(sql::api_keys::INSERT, config), &params)
+                .await?;
+            let api_key = api_key(&row);
+            println!(
+                "created key {} ({}, {})",
+                api_key.id, api_key.name, api_key.scope
+            );
+            println!("{}", key);
+            eprintln!("store this key now; it can't be shown again");
+        }
+        KeysCommand::Revoke { id } => {
+            let revoked = conn
+                .inner
+                .execute(&*sql::for_config(sql::api_keys::REVOKE, config), &[&id])
+                .await?;
+            if revoked == 0 {
+                return Err(Error::not_found(format!("active key not found: {}", id)));
+            }
+            println!("revoked key {}", id);
+        }
+        KeysCommand::List => {
+            let rows = conn
+                .inner
+                .query(&*sql::for_config(sql::api_keys::SELECT_ALL, config), &[])
+                .await?;
+            for api_key in rows.iter().map(api_key) {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    api_key.id,
+                    api_key.scope,
+                    api_key.created_at,
+                    api_key.revoked_at.as_deref().unwrap_or("active"),
+                    api_key.name,
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Row mapper for api keys listed by admins.
+fn api_key(row: &Row) -> ApiKey {
+    let scope: &str = row.get(2);
+    ApiKey {
+        id: row.get(0),
+        name: row.get(1),
+        scope: Scope::from_str(scope).unwrap_or(Scope::Read),
+        created_at: row.get(3),
+        revoked_at: row.get(4),
+    }
+}
@@ -13,6 +13,18 @@ pub mod db;
 // domain objects
 pub mod domain;
 
+// http/1.1 and http/2 listener
+pub mod server;
+
+// bulk data seeding
+pub mod seed;
+
+// api key administration
+pub mod keys;
+
+// owner backfill for stories created before ownership
+pub mod backfill;
+
 // project errors
 pub mod error;
 
@@ -3,10 +3,14 @@ static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 use bb8_todos::{
     api::{Api, Ctx},
+    backfill::{self, BackfillOptions},
     config::Config,
+    keys::{self, KeysCommand},
+    seed::{self, SeedOptions},
+    server::{Listener, Server, Tls},
 };
 use dotenvy::dotenv;
-use std::sync::Arc;
+use std::{env, process, sync::Arc, time::Duration};
 
 #[tokio::main]
 async fn main() {
@@ -18,13 +22,59 @@ async fn main() {
     let config = Arc::new(Config::default());
     tracing::debug!("Loaded config = {:?}", config);
 
+    // Dispatch subcommands; serving the api is the default
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None | Some("serve") => serve(config).await,
+        Some("seed") => {
+            let opts = SeedOptions::parse(&args[1..]).unwrap_or_else(|err| exit(err));
+            seed::run(&config, opts)
+                .await
+                .unwrap_or_else(|err| exit(err));
+        }
+        Some("backfill-owner") => {
+            let opts = BackfillOptions::parse(&args[1..]).unwrap_or_else(|err| exit(err));
+            backfill::run(&config, opts)
+                .await
+                .unwrap_or_else(|err| exit(err));
+        }
+        Some("keys") => {
+            let cmd = KeysCommand::parse(&args[1..]).unwrap_or_else(|err| exit(err));
+            keys::run(&config, cmd)
+                .await
+                .unwrap_or_else(|err| exit(err));
+        }
+        Some(cmd) => exit(format!("unknown command: {}", cmd)),
+    }
+}
+
+/// Run the api server on the main thread.
+async fn serve(config: Arc<Config>) {
     // Set up api
     let ctx = Ctx::init_from_config(Arc::clone(&config)).await.unwrap();
     let api = Api::new(Arc::new(ctx));
 
+    // Terminate TLS when certificates are configured
+    let tls = Tls::from_config(&config).unwrap_or_else(|err| exit(err));
+
     // Run a server on the main thread
-    tracing::info!("Server listening on {}", config.listen_addr);
-    axum::serve(config.tcp_listener().await, api.routes())
+    let server = Server::new(api.routes().await, &config);
+    let listener = Listener::bind(&config)
         .await
-        .unwrap();
+        .unwrap_or_else(|err| exit(format!("failed to listen: {}", err)));
+    if let Some(tls) = tls.as_ref() {
+        tls.watch(Duration::from_secs(config.tls_reload_secs));
+    }
+    tracing::info!(
+        "Server listening on {}{}",
+        listener.describe(),
+        if tls.is_some() { " with TLS" } else { "" }
+    );
+    server.serve(listener, tls).await;
+}
+
+/// Print an error and exit with a failure code.
+fn exit<E: std::fmt::Display>(err: E) -> ! {
+    eprintln!("error: {}", err);
+    process::exit(1)
 }
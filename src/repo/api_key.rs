@@ -0,0 +1,39 @@
+use crate::{
+    db::sql::Stmt,
+    domain::{Principal, Scope},
+    repo::{
+        token::{generate_token, hash_token},
+        Repo,
+    },
+    Result,
+};
+use std::str::FromStr;
+
+/// Prefix making keys recognizable, e.g. to secret scanners.
+const KEY_PREFIX: &str = "bbt_";
+
+impl Repo {
+    /// Look up the caller for an api key; `None` when the key is unknown or revoked.
+    /// Always reads from the primary so revocations take effect immediately.
+    pub async fn authenticate_key(&self, key: &str) -> Result<Option<Principal>> {
+        let conn = self.pool.get().await?;
+        let fetch_key = conn.statement(Stmt::FetchApiKey).await?;
+        let row = fetch_key
+            .query_opt(&conn.inner, &[&hash_token(key)])
+            .await?;
+        Ok(row.map(|row| {
+            let scope: &str = row.get(1);
+            let id: i32 = row.get(0);
+            Principal {
+                subject: format!("api_key:{}", id),
+                // Constrained by the table, so unknown scopes get the least access
+                scope: Scope::from_str(scope).unwrap_or(Scope::Read),
+            }
+        }))
+    }
+}
+
+/// Generate a new random api key.
+pub fn generate_key() -> String {
+    format!("{}{}", KEY_PREFIX, generate_token())
+}
@@ -0,0 +1,294 @@
+use crate::{
+    db::{pool::PgPool, sql::Stmt},
+    domain::{Status, Story, Task},
+    Error, Result,
+};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{timeout_at, Instant};
+use tokio_postgres::Row;
+
+/// Queued inserts per kind before callers wait for room.
+const QUEUE_LEN: usize = 10_000;
+
+/// A queued task insert: its story id, name, and its caller's editor filter.
+type NewTask = (i32, String, Option<String>);
+
+/// An insert waiting to be flushed, with the channel that completes its caller.
+struct Pending<T, R> {
+    item: T,
+    reply: oneshot::Sender<Result<R>>,
+}
+
+/// Coalesces concurrent story and task inserts into multi-row inserts.
+/// Inserts are collected until a batch is full or the window since the first one closes.
+pub struct WriteBatcher {
+    stories: mpsc::Sender<Pending<(String, String), Story>>,
+    tasks: mpsc::Sender<Pending<NewTask, Task>>,
+}
+
+impl WriteBatcher {
+    /// Start background flushing of batched inserts on a pool.
+    pub fn start(pool: PgPool, size: usize, window: Duration) -> Self {
+        let (stories, rx) = mpsc::channel(QUEUE_LEN);
+        tokio::spawn(run(rx, pool.clone(), size, window, flush_stories));
+
+        let (tasks, rx) = mpsc::channel(QUEUE_LEN);
+        tokio::spawn(run(rx, pool, size, window, flush_tasks));
+
+        Self { stories, tasks }
+    }
+
+    /// Insert a new story in the next batch.
+    pub async fn insert_story(&self, name: String, owner: String) -> Result<Story> {
+        submit(&self.stories, (name, owner)).await
+    }
+
+    /// Insert a new task in the next batch, if the editor, when given, may edit the story.
+    pub async fn insert_task(
+        &self,
+        story_id: i32,
+        name: String,
+        editor: Option<String>,
+    ) -> Result<Task> {
+        submit(&self.tasks, (story_id, name, editor)).await
+    }
+}
+
+/// Queue an item and wait for the batch it lands in to be flushed.
+async fn submit<T, R>(queue: &mpsc::Sender<Pending<T, R>>, item: T) -> Result<R> {
+    let (reply, rx) = oneshot::channel();
+    queue
+        .send(Pending { item, reply })
+        .await
+        .map_err(|_| Error::internal("write batcher stopped".into()))?;
+    rx.await
+        .map_err(|_| Error::internal("write batch dropped".into()))?
+}
+
+/// Collect batches from a queue and flush each one on its own pooled connection.
+async fn run<T, R, F, Fut>(
+    mut rx: mpsc::Receiver<Pending<T, R>>,
+    pool: PgPool,
+    size: usize,
+    window: Duration,
+    flush: F,
+) where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(PgPool, Vec<Pending<T, R>>) -> Fut,
+    Fut: std::future::Future<Output = ()> + Send + 'static,
+{
+    // Wait for the first insert, then fill the batch until it's full or the window closes
+    while let Some(first) = rx.recv().await {
+        let deadline = Instant::now() + window;
+        let mut batch = Vec::with_capacity(size);
+        batch.push(first);
+        while batch.len() < size {
+            match timeout_at(deadline, rx.recv()).await {
+                Ok(Some(pending)) => batch.push(pending),
+                Ok(None) | Err(_) => break,
+            }
+        }
+        tracing::debug!("flushing write batch: {}", batch.len());
+        tokio::spawn(flush(pool.clone(), batch));
+    }
+}
+
+/// Insert a batch of stories with one statement.
+async fn flush_stories(pool: PgPool, batch: Vec<Pending<(String, String), Story>>) {
+    let names: Vec<&String> = batch.iter().map(|p| &p.item.0).collect();
+    let owners: Vec<&String> = batch.iter().map(|p| &p.item.1).collect();
+    let result = async {
+        let conn = pool.get().await?;
+        let insert_stories = conn.statement(Stmt::InsertStoryBatch).await?;
+        insert_stories.query(&conn.inner, &[&names, &owners]).await
+    }
+    .await;
+
+    match result {
+        Ok(rows) => complete_stories(batch, ids_by_position(row_ids(&rows))),
+        Err(err) => fail(batch, err),
+    }
+}
+
+/// Insert a batch of tasks with one statement; tasks for missing stories, or stories their
+/// caller can't edit, are not found.
+async fn flush_tasks(pool: PgPool, batch: Vec<Pending<NewTask, Task>>) {
+    let story_ids: Vec<i32> = batch.iter().map(|p| p.item.0).collect();
+    let names: Vec<&String> = batch.iter().map(|p| &p.item.1).collect();
+    let editors: Vec<Option<&String>> = batch.iter().map(|p| p.item.2.as_ref()).collect();
+    let status: Status = Default::default();
+    let status_string = status.to_string();
+    let result = async {
+        let conn = pool.get().await?;
+        let insert_tasks = conn.statement(Stmt::InsertTaskBatch).await?;
+        insert_tasks
+            .query(&conn.inner, &[&story_ids, &names, &status_string, &editors])
+            .await
+    }
+    .await;
+
+    match result {
+        Ok(rows) => complete_tasks(batch, ids_by_position(row_ids(&rows)), status),
+        Err(err) => fail(batch, err),
+    }
+}
+
+/// Read `(id, ord)` pairs from batch insert rows.
+fn row_ids(rows: &[Row]) -> Vec<(i32, i64)> {
+    rows.iter().map(|row| (row.get(0), row.get(1))).collect()
+}
+
+/// Map inserted ids by the zero-based position of their input row; `ord` is one-based.
+fn ids_by_position(rows: Vec<(i32, i64)>) -> HashMap<usize, i32> {
+    rows.into_iter()
+        .map(|(id, ord)| (ord as usize - 1, id))
+        .collect()
+}
+
+/// Complete each story caller with the id inserted for its position.
+fn complete_stories(batch: Vec<Pending<(String, String), Story>>, mut ids: HashMap<usize, i32>) {
+    for (i, pending) in batch.into_iter().enumerate() {
+        let (name, owner) = pending.item;
+        let reply = match ids.remove(&i) {
+            Some(id) => Ok(Story::new(id, name, Some(owner))),
+            None => Err(Error::internal("story batch insert lost a row".into())),
+        };
+        let _ = pending.reply.send(reply);
+    }
+}
+
+/// Complete each task caller with the id inserted for its position.
+/// Tasks whose story doesn't exist, or can't be edited, have no row.
+fn complete_tasks(
+    batch: Vec<Pending<NewTask, Task>>,
+    mut ids: HashMap<usize, i32>,
+    status: Status,
+) {
+    for (i, pending) in batch.into_iter().enumerate() {
+        let (story_id, name, _) = pending.item;
+        let reply = match ids.remove(&i) {
+            Some(id) => Ok(Task::new(id, story_id, name, status)),
+            None => Err(Error::not_found(format!("story not found: {}", story_id))),
+        };
+        let _ = pending.reply.send(reply);
+    }
+}
+
+/// Complete every caller in a batch with the same error.
+fn fail<T, R>(batch: Vec<Pending<T, R>>, err: Error) {
+    for pending in batch {
+        let _ = pending.reply.send(Err(err.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The receivers callers wait on for their replies.
+    type Replies<R> = Vec<oneshot::Receiver<Result<R>>>;
+
+    /// Pending inserts for items, with the receivers their callers wait on.
+    fn pending<T, R>(items: Vec<T>) -> (Vec<Pending<T, R>>, Replies<R>) {
+        items
+            .into_iter()
+            .map(|item| {
+                let (reply, rx) = oneshot::channel();
+                (Pending { item, reply }, rx)
+            })
+            .unzip()
+    }
+
+    #[test]
+    fn ids_map_one_based_ords_to_positions() {
+        let ids = ids_by_position(vec![(30, 3), (10, 1), (20, 2)]);
+        assert_eq!(ids, HashMap::from([(0, 10), (1, 20), (2, 30)]));
+    }
+
+    #[test]
+    fn task_callers_get_their_own_ids() {
+        let (batch, rxs) = pending(vec![
+            (1, "a".to_string(), None),
+            (2, "b".to_string(), None),
+            (3, "c".to_string(), None),
+        ]);
+        // Returned in any order
+        let ids = ids_by_position(vec![(103, 3), (101, 1), (102, 2)]);
+        complete_tasks(batch, ids, Status::Incomplete);
+
+        for (rx, (id, story_id, name)) in
+            rxs.into_iter()
+                .zip([(101, 1, "a"), (102, 2, "b"), (103, 3, "c")])
+        {
+            let task = rx.blocking_recv().unwrap().unwrap();
+            assert_eq!(
+                (task.id, task.story_id, task.name.as_str()),
+                (id, story_id, name)
+            );
+        }
+    }
+
+    #[test]
+    fn skipped_tasks_are_not_found() {
+        let (batch, rxs) = pending(vec![
+            (1, "a".to_string(), None),
+            (2, "b".to_string(), Some("user:1".to_string())),
+            (3, "c".to_string(), None),
+            (4, "d".to_string(), None),
+        ]);
+        // The second and fourth rows were skipped
+        let ids = ids_by_position(vec![(203, 3), (201, 1)]);
+        complete_tasks(batch, ids, Status::Incomplete);
+
+        let replies: Vec<_> = rxs
+            .into_iter()
+            .map(|rx| rx.blocking_recv().unwrap())
+            .collect();
+        assert_eq!(replies[0].as_ref().unwrap().id, 201);
+        assert!(
+            matches!(&replies[1], Err(Error::NotFound { message }) if message == "story not found: 2")
+        );
+        assert_eq!(replies[2].as_ref().unwrap().id, 203);
+        assert!(
+            matches!(&replies[3], Err(Error::NotFound { message }) if message == "story not found: 4")
+        );
+    }
+
+    #[test]
+    fn story_callers_get_their_own_ids() {
+        let (batch, rxs) = pending(vec![
+            ("a".to_string(), "user:1".to_string()),
+            ("b".to_string(), "user:2".to_string()),
+        ]);
+        complete_stories(batch, ids_by_position(vec![(8, 2), (7, 1)]));
+
+        let stories: Vec<_> = rxs
+            .into_iter()
+            .map(|rx| rx.blocking_recv().unwrap().unwrap())
+            .collect();
+        assert_eq!((stories[0].id, stories[0].name.as_str()), (7, "a"));
+        assert_eq!(
+            (stories[1].id, stories[1].owner.as_deref()),
+            (8, Some("user:2"))
+        );
+    }
+
+    #[test]
+    fn lost_story_rows_are_internal_errors() {
+        let (batch, rxs) = pending(vec![
+            ("a".to_string(), "user:1".to_string()),
+            ("b".to_string(), "user:1".to_string()),
+        ]);
+        complete_stories(batch, ids_by_position(vec![(7, 1)]));
+
+        let replies: Vec<_> = rxs
+            .into_iter()
+            .map(|rx| rx.blocking_recv().unwrap())
+            .collect();
+        assert_eq!(replies[0].as_ref().unwrap().id, 7);
+        assert!(matches!(replies[1], Err(Error::Internal { .. })));
+    }
+}
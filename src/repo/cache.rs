@@ -0,0 +1,436 @@
+use crate::{
+    db::pool::PgPool,
+    domain::{Story, Task},
+    Result,
+};
+use futures::{stream, StreamExt};
+use moka::sync::Cache;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_postgres::{AsyncMessage, NoTls};
+
+/// Notification channel for invalidations shared by all instances.
+const CHANNEL: &str = "bb8_todos_cache";
+
+/// Page id of the first page of stories, the only page that is cached.
+pub const FIRST_PAGE: i32 = 1;
+
+/// A page of stories with previous and next page cursors.
+pub type StoryPage = (i32, i32, Vec<Story>);
+
+/// Generation counters per kind of entry; keys share counters by hash.
+const GENERATION_STRIPES: usize = 1024;
+
+/// The generation of an entry, taken before reading it from the database.
+/// A fill is dropped when the entry was invalidated after its ticket was taken,
+/// so a read that raced a write can't bring back a stale entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ticket(u64);
+
+/// Generation counters bumped by every invalidation of the keys they cover.
+struct Generations(Vec<AtomicU64>);
+
+impl Generations {
+    fn new(stripes: usize) -> Self {
+        Self((0..stripes).map(|_| AtomicU64::new(0)).collect())
+    }
+
+    fn stripe(&self, key: i32) -> &AtomicU64 {
+        &self.0[key as u32 as usize % self.0.len()]
+    }
+
+    fn ticket(&self, key: i32) -> Ticket {
+        Ticket(self.stripe(key).load(Ordering::SeqCst))
+    }
+
+    fn bump(&self, key: i32) {
+        self.stripe(key).fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn bump_all(&self) {
+        for stripe in &self.0 {
+            stripe.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Insert unless invalidated since the ticket was taken. Checked again after inserting,
+    /// since an invalidation may bump and evict between the first check and the insert.
+    fn fill<V: Clone + Send + Sync + 'static>(
+        &self,
+        cache: &Cache<i32, V>,
+        key: i32,
+        ticket: Ticket,
+        value: &V,
+    ) {
+        if self.ticket(key) != ticket {
+            return;
+        }
+        cache.insert(key, value.clone());
+        if self.ticket(key) != ticket {
+            cache.invalidate(&key);
+        }
+    }
+}
+
+/// A cached entry made stale by a write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Invalidation {
+    Story(i32),
+    Task(i32),
+    StoryTasks(i32),
+    FirstPage,
+}
+
+impl Invalidation {
+    /// Encode as a notification payload.
+    fn encode(self) -> String {
+        match self {
+            Self::Story(id) => format!("story:{}", id),
+            Self::Task(id) => format!("task:{}", id),
+            Self::StoryTasks(id) => format!("story_tasks:{}", id),
+            Self::FirstPage => "first_page".into(),
+        }
+    }
+
+    /// Decode a notification payload.
+    fn decode(payload: &str) -> Option<Self> {
+        if payload == "first_page" {
+            return Some(Self::FirstPage);
+        }
+        let (kind, id) = payload.split_once(':')?;
+        let id = id.parse().ok()?;
+        match kind {
+            "story" => Some(Self::Story(id)),
+            "task" => Some(Self::Task(id)),
+            "story_tasks" => Some(Self::StoryTasks(id)),
+            _ => None,
+        }
+    }
+}
+
+/// A bounded in-memory cache of hot reads with a TTL.
+/// Writes invalidate entries locally and publish the invalidation to other instances.
+/// Fills need a ticket taken before the read, and are only made from primary reads.
+pub struct ReadCache {
+    stories: Cache<i32, Story>,
+    tasks: Cache<i32, Task>,
+    pages: Cache<i32, StoryPage>,
+    story_generations: Generations,
+    task_generations: Generations,
+    page_generations: Generations,
+    publish: Option<PgPool>,
+}
+
+impl ReadCache {
+    /// Create a cache holding up to `capacity` entries of each kind.
+    pub fn new(capacity: u64, ttl: Duration) -> Self {
+        Self {
+            stories: Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(ttl)
+                .build(),
+            tasks: Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(ttl)
+                .support_invalidation_closures()
+                .build(),
+            pages: Cache::builder().max_capacity(1).time_to_live(ttl).build(),
+            story_generations: Generations::new(GENERATION_STRIPES),
+            task_generations: Generations::new(GENERATION_STRIPES),
+            page_generations: Generations::new(1),
+            publish: None,
+        }
+    }
+
+    /// Publish invalidations to other instances with NOTIFY on a pool.
+    pub fn with_publish(mut self, pool: PgPool) -> Self {
+        self.publish = Some(pool);
+        self
+    }
+
+    /// Get a cached story.
+    pub fn story(&self, id: i32) -> Option<Story> {
+        self.stories.get(&id)
+    }
+
+    /// Take a ticket for filling a story.
+    pub fn story_ticket(&self, id: i32) -> Ticket {
+        self.story_generations.ticket(id)
+    }
+
+    /// Cache a story, unless invalidated since the ticket was taken.
+    pub fn put_story(&self, ticket: Ticket, story: &Story) {
+        self.story_generations
+            .fill(&self.stories, story.id, ticket, story);
+    }
+
+    /// Get a cached task.
+    pub fn task(&self, id: i32) -> Option<Task> {
+        self.tasks.get(&id)
+    }
+
+    /// Take a ticket for filling a task.
+    pub fn task_ticket(&self, id: i32) -> Ticket {
+        self.task_generations.ticket(id)
+    }
+
+    /// Cache a task, unless invalidated since the ticket was taken.
+    pub fn put_task(&self, ticket: Ticket, task: &Task) {
+        self.task_generations
+            .fill(&self.tasks, task.id, ticket, task);
+    }
+
+    /// Get the cached first page of stories.
+    pub fn first_page(&self) -> Option<StoryPage> {
+        self.pages.get(&FIRST_PAGE)
+    }
+
+    /// Take a ticket for filling the first page of stories.
+    pub fn first_page_ticket(&self) -> Ticket {
+        self.page_generations.ticket(FIRST_PAGE)
+    }
+
+    /// Cache the first page of stories, unless invalidated since the ticket was taken.
+    pub fn put_first_page(&self, ticket: Ticket, page: &StoryPage) {
+        self.page_generations
+            .fill(&self.pages, FIRST_PAGE, ticket, page);
+    }
+
+    /// Drop stale entries here and on every other instance.
+    pub fn invalidate(&self, invalidations: &[Invalidation]) {
+        if invalidations.is_empty() {
+            return;
+        }
+        for invalidation in invalidations {
+            self.evict(*invalidation);
+        }
+        if let Some(pool) = self.publish.clone() {
+            let payloads: Vec<_> = invalidations.iter().map(|i| i.encode()).collect();
+            tokio::spawn(async move {
+                if let Err(err) = publish(&pool, &payloads).await {
+                    tracing::warn!("failed to publish cache invalidations: {}", err);
+                }
+            });
+        }
+    }
+
+    /// Drop stale entries from this instance only.
+    /// Generations are bumped first, so fills racing the eviction are dropped too.
+    fn evict(&self, invalidation: Invalidation) {
+        match invalidation {
+            Invalidation::Story(id) => {
+                self.story_generations.bump(id);
+                self.stories.invalidate(&id);
+            }
+            Invalidation::Task(id) => {
+                self.task_generations.bump(id);
+                self.tasks.invalidate(&id);
+            }
+            Invalidation::StoryTasks(story_id) => {
+                self.task_generations.bump_all();
+                let result = self
+                    .tasks
+                    .invalidate_entries_if(move |_, task| task.story_id == story_id);
+                if result.is_err() {
+                    self.tasks.invalidate_all();
+                }
+            }
+            Invalidation::FirstPage => {
+                self.page_generations.bump(FIRST_PAGE);
+                self.pages.invalidate(&FIRST_PAGE);
+            }
+        }
+    }
+
+    /// Drop everything, e.g. when invalidations from other instances may have been missed.
+    fn clear(&self) {
+        self.story_generations.bump_all();
+        self.task_generations.bump_all();
+        self.page_generations.bump_all();
+        self.stories.invalidate_all();
+        self.tasks.invalidate_all();
+        self.pages.invalidate_all();
+    }
+
+    /// Apply invalidations published by other instances, reconnecting when the listener drops.
+    pub fn listen(self: &Arc<Self>, db_url: String) {
+        let cache = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                if let Err(err) = cache.listen_once(&db_url).await {
+                    tracing::warn!("cache invalidation listener failed: {}", err);
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        });
+    }
+
+    /// Listen on a dedicated connection until it closes.
+    async fn listen_once(&self, db_url: &str) -> Result<()> {
+        let (client, mut connection) = tokio_postgres::connect(db_url, NoTls).await?;
+
+        // Forward notifications while the connection is driven in the background
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+        tokio::spawn(async move {
+            while let Some(Ok(message)) = messages.next().await {
+                if let AsyncMessage::Notification(n) = message {
+                    let _ = tx.send(n.payload().to_string());
+                }
+            }
+        });
+
+        client.batch_execute(&format!("listen {}", CHANNEL)).await?;
+
+        // Anything could have changed while not listening
+        self.clear();
+
+        while let Some(payload) = rx.recv().await {
+            match Invalidation::decode(&payload) {
+                Some(invalidation) => self.evict(invalidation),
+                None => tracing::warn!("unknown cache invalidation: {}", payload),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Send invalidations to other instances in one round-trip.
+async fn publish(pool: &PgPool, payloads: &[String]) -> Result<()> {
+    let sql: String = payloads
+        .iter()
+        .map(|p| format!("select pg_notify('{}', '{}');", CHANNEL, p))
+        .collect();
+    let conn = pool.get().await?;
+    conn.inner.batch_execute(&sql).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Status;
+
+    fn cache() -> ReadCache {
+        ReadCache::new(100, Duration::from_secs(60))
+    }
+
+    fn story(id: i32) -> Story {
+        Story::new(id, format!("story {}", id), Some("user:1".into()))
+    }
+
+    fn task(id: i32, story_id: i32) -> Task {
+        Task::new(id, story_id, format!("task {}", id), Status::Incomplete)
+    }
+
+    #[test]
+    fn fills_with_a_current_ticket() {
+        let cache = cache();
+        let ticket = cache.story_ticket(1);
+        cache.put_story(ticket, &story(1));
+        assert_eq!(cache.story(1), Some(story(1)));
+    }
+
+    #[test]
+    fn drops_fills_with_a_ticket_from_before_an_invalidation() {
+        let cache = cache();
+        let story_ticket = cache.story_ticket(1);
+        let task_ticket = cache.task_ticket(2);
+        let page_ticket = cache.first_page_ticket();
+
+        // A write lands while the reads are in flight
+        cache.invalidate(&[
+            Invalidation::Story(1),
+            Invalidation::Task(2),
+            Invalidation::FirstPage,
+        ]);
+        cache.put_story(story_ticket, &story(1));
+        cache.put_task(task_ticket, &task(2, 1));
+        cache.put_first_page(page_ticket, &(0, 0, vec![story(1)]));
+
+        assert_eq!(cache.story(1), None);
+        assert_eq!(cache.task(2), None);
+        assert_eq!(cache.first_page(), None);
+
+        // A read started after the write may fill
+        cache.put_story(cache.story_ticket(1), &story(1));
+        assert_eq!(cache.story(1), Some(story(1)));
+    }
+
+    #[test]
+    fn story_tasks_invalidation_drops_task_fills_in_flight() {
+        let cache = cache();
+        cache.put_task(cache.task_ticket(1), &task(1, 7));
+        cache.put_task(cache.task_ticket(2), &task(2, 8));
+        let ticket = cache.task_ticket(3);
+
+        cache.invalidate(&[Invalidation::StoryTasks(7)]);
+        cache.put_task(ticket, &task(3, 7));
+
+        assert_eq!(cache.task(1), None);
+        assert_eq!(cache.task(2), Some(task(2, 8)));
+        assert_eq!(cache.task(3), None);
+    }
+
+    #[test]
+    fn entries_expire_after_the_ttl() {
+        let cache = ReadCache::new(100, Duration::from_millis(50));
+        cache.put_story(cache.story_ticket(1), &story(1));
+        cache.put_task(cache.task_ticket(1), &task(1, 1));
+        cache.put_first_page(cache.first_page_ticket(), &(0, 0, vec![story(1)]));
+        assert!(cache.story(1).is_some());
+
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(cache.story(1), None);
+        assert_eq!(cache.task(1), None);
+        assert_eq!(cache.first_page(), None);
+    }
+
+    #[test]
+    fn clearing_on_listener_reconnect_drops_everything() {
+        let cache = cache();
+        cache.put_story(cache.story_ticket(1), &story(1));
+        cache.put_task(cache.task_ticket(2), &task(2, 1));
+        cache.put_first_page(cache.first_page_ticket(), &(0, 0, vec![story(1)]));
+        let ticket = cache.story_ticket(3);
+
+        cache.clear();
+        cache.put_story(ticket, &story(3));
+
+        assert_eq!(cache.story(1), None);
+        assert_eq!(cache.task(2), None);
+        assert_eq!(cache.first_page(), None);
+        assert_eq!(cache.story(3), None);
+    }
+
+    #[test]
+    fn only_the_first_page_is_cached() {
+        let cache = cache();
+        let page = (0, 101, vec![story(1)]);
+        cache.put_first_page(cache.first_page_ticket(), &page);
+        cache.pages.run_pending_tasks();
+
+        assert_eq!(cache.first_page(), Some(page));
+        let keys: Vec<i32> = cache.pages.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![FIRST_PAGE]);
+    }
+
+    #[test]
+    fn invalidations_round_trip_through_payloads() {
+        for invalidation in [
+            Invalidation::Story(1),
+            Invalidation::Task(2),
+            Invalidation::StoryTasks(3),
+            Invalidation::FirstPage,
+        ] {
+            assert_eq!(
+                Invalidation::decode(&invalidation.encode()),
+                Some(invalidation)
+            );
+        }
+        assert_eq!(Invalidation::decode("story:x"), None);
+        assert_eq!(Invalidation::decode("other:1"), None);
+    }
+}
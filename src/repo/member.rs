@@ -0,0 +1,179 @@
+use crate::{
+    db::{pool::connection::PgConn, sql::Stmt},
+    domain::{Member, Principal, Role, Story, Task},
+    repo::{story, Repo},
+    Error, Result,
+};
+use std::str::FromStr;
+
+impl Repo {
+    /// The caller's role on a story; `None` when it doesn't exist or isn't shared with them.
+    /// Memberships are read from the primary so removals take effect immediately.
+    pub async fn story_role(&self, story: &Story, principal: &Principal) -> Result<Option<Role>> {
+        if principal.owns(story) {
+            return Ok(Some(Role::Owner));
+        }
+        fetch_role(&*self.pool.get().await?, story.id, &principal.subject).await
+    }
+
+    /// Select a story the caller may view; stories they have no role on are not found.
+    pub async fn select_story_for(&self, id: i32, principal: &Principal) -> Result<Story> {
+        let story = self.select_story(id).await?;
+        let role = self.story_role(&story, principal).await?;
+        Role::require(role, Role::Viewer, id)?;
+        Ok(story)
+    }
+
+    /// Check that the caller's role on a story allows an action, as not found when they
+    /// have no role and forbidden when it's too low. Admins skip the lookup.
+    pub async fn check_story_access(
+        &self,
+        id: i32,
+        principal: &Principal,
+        needed: Role,
+    ) -> Result<()> {
+        if principal.sees_all() {
+            return Ok(());
+        }
+        let story = self.select_story(id).await?;
+        let role = self.story_role(&story, principal).await?;
+        Role::require(role, needed, id)
+    }
+
+    /// Explain a write whose role check matched nothing: not found when the story is gone or
+    /// the caller has no role, forbidden when it's too low. Other results pass through.
+    /// The lookup only picks the error; the write itself already refused.
+    pub(super) async fn or_denied<T>(
+        &self,
+        result: Result<T>,
+        story_id: i32,
+        principal: &Principal,
+        needed: Role,
+    ) -> Result<T> {
+        let Err(Error::NotFound { .. }) = result else {
+            return result;
+        };
+        let conn = self.pool.get().await?;
+        let story = story::fetch(&conn, story_id).await?;
+        let role = if principal.owns(&story) {
+            Some(Role::Owner)
+        } else {
+            fetch_role(&conn, story_id, &principal.subject).await?
+        };
+        Role::require(role, needed, story_id)?;
+        // Allowed by now, so access changed after the write; report what the write saw
+        result
+    }
+
+    /// Select a task whose story allows an action; tasks in stories the caller has no role
+    /// on are not found.
+    pub async fn select_task_for(
+        &self,
+        id: i32,
+        principal: &Principal,
+        needed: Role,
+    ) -> Result<Task> {
+        let task = self.select_task(id).await?;
+        self.check_task_access(task, principal, needed).await
+    }
+
+    /// Like `select_task_for`, but reads the task uncached for read-modify-write updates.
+    pub async fn select_task_for_update(
+        &self,
+        id: i32,
+        principal: &Principal,
+        needed: Role,
+    ) -> Result<Task> {
+        let task = self.select_task_uncached(id).await?;
+        self.check_task_access(task, principal, needed).await
+    }
+
+    async fn check_task_access(
+        &self,
+        task: Task,
+        principal: &Principal,
+        needed: Role,
+    ) -> Result<Task> {
+        match self
+            .check_story_access(task.story_id, principal, needed)
+            .await
+        {
+            Err(Error::NotFound { .. }) => {
+                Err(Error::not_found(format!("task not found: {}", task.id)))
+            }
+            result => result.map(|_| task),
+        }
+    }
+
+    /// Select the members a story is shared with.
+    pub async fn select_members(&self, story_id: i32) -> Result<Vec<Member>> {
+        let conn = self.pool.get().await?;
+        let select_members = conn.statement(Stmt::SelectMembers).await?;
+
+        let rows = select_members.query(&conn.inner, &[&story_id]).await?;
+        Ok(rows
+            .iter()
+            .map(|row| Member::new(story_id, row.get(0), to_role(row.get(1))))
+            .collect())
+    }
+
+    /// Share a story with a member.
+    pub async fn insert_member(&self, story_id: i32, member: String, role: Role) -> Result<Member> {
+        tracing::debug!("insert_member: {} {} {}", story_id, member, role);
+
+        let conn = self.pool.get().await?;
+        let insert_member = conn.statement(Stmt::InsertMember).await?;
+
+        let role_string = role.to_string();
+        match insert_member
+            .query_opt(&conn.inner, &[&story_id, &member, &role_string])
+            .await?
+        {
+            Some(_) => Ok(Member::new(story_id, member, role)),
+            None => Err(Error::conflict(format!("already a member: {}", member))),
+        }
+    }
+
+    /// Change a member's role on a story.
+    pub async fn update_member(&self, story_id: i32, member: String, role: Role) -> Result<Member> {
+        tracing::debug!("update_member: {} {} {}", story_id, member, role);
+
+        let conn = self.pool.get().await?;
+        let update_member = conn.statement(Stmt::UpdateMemberRole).await?;
+
+        let role_string = role.to_string();
+        match update_member
+            .query_opt(&conn.inner, &[&story_id, &member, &role_string])
+            .await?
+        {
+            Some(_) => Ok(Member::new(story_id, member, role)),
+            None => Err(Error::not_found(format!("member not found: {}", member))),
+        }
+    }
+
+    /// Stop sharing a story with a member.
+    pub async fn delete_member(&self, story_id: i32, member: &str) -> Result<u64> {
+        tracing::debug!("delete_member: {} {}", story_id, member);
+
+        let conn = self.pool.get().await?;
+        let delete_member = conn.statement(Stmt::DeleteMember).await?;
+        delete_member
+            .execute(&conn.inner, &[&story_id, &member])
+            .await
+    }
+}
+
+/// Select a member's role on a story, if any.
+pub(super) async fn fetch_role(conn: &PgConn, story_id: i32, member: &str) -> Result<Option<Role>> {
+    let fetch_role = conn.statement(Stmt::FetchMemberRole).await?;
+
+    let row = fetch_role
+        .query_opt(&conn.inner, &[&story_id, &member])
+        .await?;
+    Ok(row.map(|row| to_role(row.get(0))))
+}
+
+/// Constrained by the table, so unknown roles get the least access.
+fn to_role(role: &str) -> Role {
+    Role::from_str(role).unwrap_or(Role::Viewer)
+}
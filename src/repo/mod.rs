@@ -1,17 +1,107 @@
-use crate::db::pool::PgPool;
+use crate::db::pool::{PgPool, PgPooledConn};
+use crate::domain::Story;
+use cache::{Invalidation, Ticket};
+use single_flight::SingleFlight;
+use std::sync::Arc;
 
+mod api_key;
+mod batcher;
+mod cache;
+mod member;
+mod replica;
+mod single_flight;
 mod story;
 mod task;
+mod token;
+mod uow;
+mod user;
+
+pub use api_key::generate_key;
+pub use batcher::WriteBatcher;
+pub use cache::ReadCache;
+pub use replica::{read_primary, Replica};
+pub use single_flight::SingleFlightStats;
+pub use token::{generate_token, hash_token};
+pub use uow::UnitOfWork;
 
 /// A thin abstraction layer over the database schema.
 /// Maps query results to domain objects.
 pub struct Repo {
     pool: PgPool,
+    replica: Option<Arc<Replica>>,
+    batcher: Option<WriteBatcher>,
+    story_reads: SingleFlight<(i32, bool), (Story, Option<Ticket>)>,
+    cache: Option<Arc<ReadCache>>,
 }
 
 impl Repo {
     /// Create a new repo.
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            replica: None,
+            batcher: None,
+            story_reads: SingleFlight::new(),
+            cache: None,
+        }
+    }
+
+    /// Route reads to a replica when it is healthy.
+    pub fn with_replica(mut self, replica: Arc<Replica>) -> Self {
+        self.replica = Some(replica);
+        self
+    }
+
+    /// Coalesce concurrent story and task inserts into batches.
+    pub fn with_batcher(mut self, batcher: WriteBatcher) -> Self {
+        self.batcher = Some(batcher);
+        self
+    }
+
+    /// Serve hot reads from an in-memory cache that writes invalidate.
+    pub fn with_cache(mut self, cache: Arc<ReadCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Whether a read replica is configured.
+    pub fn has_replica(&self) -> bool {
+        self.replica.is_some()
+    }
+
+    /// Counts of story reads that queried the database and that shared an in-flight query.
+    pub fn story_read_stats(&self) -> SingleFlightStats {
+        self.story_reads.stats()
+    }
+
+    /// The cache to serve reads from; skipped for reads pinned to the primary,
+    /// since other instances' invalidations for a recent write may not have arrived yet.
+    fn read_cache(&self) -> Option<&ReadCache> {
+        self.cache
+            .as_deref()
+            .filter(|_| !replica::is_read_primary())
+    }
+
+    /// Drop cached entries made stale by a write.
+    fn invalidate(&self, invalidations: &[Invalidation]) {
+        if let Some(cache) = self.cache.as_ref() {
+            cache.invalidate(invalidations);
+        }
+    }
+
+    /// Check out a connection for reads, preferring a usable replica.
+    async fn read_conn(&self) -> crate::Result<PgPooledConn> {
+        if let Some(replica) = self.replica.as_ref() {
+            if replica.is_usable() && !replica::is_read_primary() {
+                match replica.pool.get().await {
+                    Ok(conn) => return Ok(conn),
+                    Err(err) => {
+                        tracing::warn!("replica unavailable, reading from primary: {}", err);
+                        replica.mark_unhealthy();
+                    }
+                }
+            }
+        }
+        self.pool.get().await
     }
 }
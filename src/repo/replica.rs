@@ -0,0 +1,89 @@
+use crate::{db::pool::PgPool, db::sql, Error, Result};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_postgres::SimpleQueryMessage;
+
+tokio::task_local! {
+    /// Set for requests that must read from the primary.
+    static READ_PRIMARY: bool;
+}
+
+/// Run a future with all repo reads routed to the primary.
+pub async fn read_primary<F: Future>(f: F) -> F::Output {
+    READ_PRIMARY.scope(true, f).await
+}
+
+/// Whether the current task has asked for reads from the primary.
+pub(crate) fn is_read_primary() -> bool {
+    READ_PRIMARY.try_with(|b| *b).unwrap_or(false)
+}
+
+/// A read replica pool with health and lag tracking.
+pub struct Replica {
+    pub pool: PgPool,
+    max_lag_ms: u64,
+    healthy: AtomicBool,
+    lag_ms: AtomicU64,
+}
+
+impl Replica {
+    /// Create a new replica; considered unhealthy until the first check passes.
+    pub fn new(pool: PgPool, max_lag_ms: u64) -> Self {
+        Self {
+            pool,
+            max_lag_ms,
+            healthy: AtomicBool::new(false),
+            lag_ms: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether reads can be routed to the replica.
+    pub fn is_usable(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+            && self.lag_ms.load(Ordering::Relaxed) <= self.max_lag_ms
+    }
+
+    /// Take the replica out of rotation until the next successful check.
+    pub fn mark_unhealthy(&self) {
+        self.healthy.store(false, Ordering::Relaxed);
+    }
+
+    /// Query the replica for its current replication lag.
+    async fn check(&self) -> Result<u64> {
+        let conn = self.pool.get().await?;
+        let messages = conn.simple_query(sql::REPLICA_LAG).await?;
+        messages
+            .iter()
+            .find_map(|m| match m {
+                SimpleQueryMessage::Row(row) => row.get(0).and_then(|s| s.parse().ok()),
+                _ => None,
+            })
+            .ok_or_else(|| Error::internal("unable to read replica lag".into()))
+    }
+
+    /// Periodically check replica health and lag in the background.
+    pub fn monitor(self: &Arc<Self>, period: Duration) {
+        let replica = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                match replica.check().await {
+                    Ok(lag_ms) => {
+                        if lag_ms > replica.max_lag_ms {
+                            tracing::warn!("replica lagging: {}ms", lag_ms);
+                        }
+                        replica.lag_ms.store(lag_ms, Ordering::Relaxed);
+                        replica.healthy.store(true, Ordering::Relaxed);
+                    }
+                    Err(err) => {
+                        tracing::warn!("replica check failed: {}", err);
+                        replica.mark_unhealthy();
+                    }
+                }
+            }
+        });
+    }
+}
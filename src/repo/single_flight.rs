@@ -0,0 +1,190 @@
+use crate::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+/// The result of an in-flight call, published once the leader finishes.
+type Slot<V> = watch::Receiver<Option<Result<V>>>;
+
+/// Shares one in-flight call between concurrent callers asking for the same key.
+/// The first caller leads and runs the call; the others wait for its result.
+pub struct SingleFlight<K, V> {
+    inflight: Mutex<HashMap<K, Slot<V>>>,
+    leaders: AtomicU64,
+    coalesced: AtomicU64,
+}
+
+/// Counts of calls that ran and calls that shared another caller's result.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SingleFlightStats {
+    pub leaders: u64,
+    pub coalesced: u64,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> SingleFlight<K, V> {
+    /// Create an empty single-flight group.
+    pub fn new() -> Self {
+        Self {
+            inflight: Mutex::new(HashMap::new()),
+            leaders: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+        }
+    }
+
+    /// Run a call, or wait for the result of an identical one already in flight.
+    pub async fn run<F, Fut>(&self, key: K, call: F) -> Result<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V>>,
+    {
+        let leader = {
+            let mut inflight = self.inflight.lock().unwrap();
+            match inflight.get(&key) {
+                Some(slot) => Err(slot.clone()),
+                None => {
+                    let (tx, rx) = watch::channel(None);
+                    inflight.insert(key.clone(), rx);
+                    Ok(tx)
+                }
+            }
+        };
+
+        match leader {
+            Ok(tx) => {
+                self.leaders.fetch_add(1, Ordering::Relaxed);
+                let _guard = Remove { group: self, key };
+                let result = call().await;
+                tx.send_replace(Some(result.clone()));
+                result
+            }
+            Err(mut slot) => {
+                self.coalesced.fetch_add(1, Ordering::Relaxed);
+                let shared = slot.wait_for(Option::is_some).await.map(|r| r.clone());
+                match shared {
+                    Ok(result) => result.expect("result published"),
+                    // The leader was cancelled before finishing; run the call directly
+                    Err(_) => call().await,
+                }
+            }
+        }
+    }
+
+    /// Snapshot of call counts.
+    pub fn stats(&self) -> SingleFlightStats {
+        SingleFlightStats {
+            leaders: self.leaders.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Clears the in-flight entry when the leader finishes or is cancelled.
+struct Remove<'a, K: Eq + Hash, V> {
+    group: &'a SingleFlight<K, V>,
+    key: K,
+}
+
+impl<K: Eq + Hash, V> Drop for Remove<'_, K, V> {
+    fn drop(&mut self) {
+        self.group.inflight.lock().unwrap().remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn concurrent_callers_share_one_call() {
+        let group = SingleFlight::<i32, i32>::new();
+        let calls = AtomicUsize::new(0);
+        let (release, gate) = oneshot::channel::<()>();
+
+        // Polled in order, so the first call leads and the others find it in flight
+        let call = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok(5)
+        };
+        let leader = group.run(1, || async {
+            let _ = gate.await;
+            call().await
+        });
+        let (a, b, c, _) = tokio::join!(leader, group.run(1, call), group.run(1, call), async {
+            tokio::task::yield_now().await;
+            let _ = release.send(());
+        });
+
+        assert_eq!((a.unwrap(), b.unwrap(), c.unwrap()), (5, 5, 5));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let stats = group.stats();
+        assert_eq!((stats.leaders, stats.coalesced), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn follower_runs_the_call_when_the_leader_is_cancelled() {
+        let group = Arc::new(SingleFlight::<i32, i32>::new());
+        let (started, leading) = oneshot::channel();
+
+        let leader = tokio::spawn({
+            let group = Arc::clone(&group);
+            async move {
+                group
+                    .run(1, || async {
+                        let _ = started.send(());
+                        std::future::pending::<Result<i32>>().await
+                    })
+                    .await
+            }
+        });
+        leading.await.unwrap();
+
+        let follower = tokio::spawn({
+            let group = Arc::clone(&group);
+            async move { group.run(1, || async { Ok(7) }).await }
+        });
+        while group.stats().coalesced == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        leader.abort();
+        assert_eq!(follower.await.unwrap().unwrap(), 7);
+        // The cancelled leader's entry is gone, so the next caller leads
+        assert_eq!(group.run(1, || async { Ok(8) }).await.unwrap(), 8);
+        assert_eq!(group.stats().leaders, 2);
+    }
+
+    #[tokio::test]
+    async fn errors_reach_every_waiter() {
+        let group = SingleFlight::<i32, i32>::new();
+        let (release, gate) = oneshot::channel::<()>();
+
+        let leader = group.run(1, || async {
+            let _ = gate.await;
+            Err(Error::not_found("story not found: 1".into()))
+        });
+        let (a, b, _) = tokio::join!(leader, group.run(1, || async { Ok(1) }), async {
+            tokio::task::yield_now().await;
+            let _ = release.send(());
+        });
+
+        for result in [a, b] {
+            assert!(
+                matches!(result, Err(Error::NotFound { message }) if message == "story not found: 1")
+            );
+        }
+    }
+}
@@ -1,119 +1,238 @@
-use crate::{domain::Story, repo::Repo, Error, Result};
-use futures::StreamExt;
-use tokio::pin;
-
-use crate::db::sql;
+use crate::{
+    db::pool::connection::PgConn,
+    domain::{Principal, Role, SharedStory, Story},
+    repo::{
+        cache::{Invalidation, StoryPage, FIRST_PAGE},
+        replica, Repo,
+    },
+    Error, Result,
+};
+
+use crate::db::sql::Stmt;
+use std::str::FromStr;
+use tokio_postgres::Row;
 
 const PAGE_SIZE: usize = 100;
 
 impl Repo {
     /// Select a story by id
+    /// Concurrent reads of the same story share one query.
+    /// With a cache, misses read the primary so entries are never filled from a lagging replica.
     pub async fn select_story(&self, id: i32) -> Result<Story> {
-        tracing::debug!("select_story: {}", id);
-
-        let mut conn = self.pool.get().await?;
-        let select_story = conn.prepare_cache(sql::stories::FETCH).await?;
-
-        let stream = conn.query_raw(&select_story, &[&id]).await?;
-        pin!(stream);
+        if let Some(story) = self.read_cache().and_then(|c| c.story(id)) {
+            return Ok(story);
+        }
+        let primary = replica::is_read_primary() || self.cache.is_some();
+        let (story, ticket) = self
+            .story_reads
+            .run((id, primary), || async {
+                // Taken by whoever runs the query, so followers can't fill with an older read
+                let ticket = self.cache.as_ref().map(|c| c.story_ticket(id));
+                let conn = if primary {
+                    self.pool.get().await?
+                } else {
+                    self.read_conn().await?
+                };
+                Ok((fetch(&conn, id).await?, ticket))
+            })
+            .await?;
+        if let (Some(cache), Some(ticket)) = (self.cache.as_ref(), ticket) {
+            cache.put_story(ticket, &story);
+        }
+        Ok(story)
+    }
 
-        if let Some(result) = stream.next().await {
-            let row = result?;
-            Ok(Story::new(row.get(0), row.get(1)))
-        } else {
-            Err(Error::not_found(format!("story not found: {}", id)))
+    /// Select a page of stories with previous and next page cursors,
+    /// limited to one owner's stories when given. Only the unfiltered first page is cached.
+    pub async fn select_stories(&self, page_id: i32, owner: Option<&str>) -> Result<StoryPage> {
+        if let Some(owner) = owner {
+            return self.query_owned_stories(page_id, owner).await;
+        }
+        if page_id != FIRST_PAGE {
+            return self.query_stories(page_id).await;
+        }
+        if let Some(page) = self.read_cache().and_then(|c| c.first_page()) {
+            return Ok(page);
         }
+        let Some(cache) = self.cache.as_ref() else {
+            return self.query_stories(page_id).await;
+        };
+        // Filled from the primary, like stories
+        let ticket = cache.first_page_ticket();
+        let page = replica::read_primary(self.query_stories(page_id)).await?;
+        cache.put_first_page(ticket, &page);
+        Ok(page)
     }
 
-    /// Select a page of stories with previous and next page cursors.
-    pub async fn select_stories(&self, page_id: i32) -> Result<(i32, i32, Vec<Story>)> {
+    async fn query_stories(&self, page_id: i32) -> Result<StoryPage> {
         tracing::debug!("select_stories");
 
-        let mut conn = self.pool.get().await?;
-        let select_stories = conn.prepare_cache(sql::stories::SELECT).await?;
+        let conn = self.read_conn().await?;
+        let select_stories = conn.statement(Stmt::SelectStories).await?;
 
-        let stream = conn.query_raw(&select_stories, &[page_id]).await?;
-        pin!(stream);
+        let rows = select_stories.query(&conn.inner, &[&page_id]).await?;
+        Ok(to_page(rows, to_story))
+    }
 
-        let mut prev_pid: i32 = 0;
-        let mut next_pid: i32 = 0;
-        let mut stories = Vec::with_capacity(PAGE_SIZE);
+    async fn query_owned_stories(&self, page_id: i32, owner: &str) -> Result<StoryPage> {
+        tracing::debug!("select_owned_stories: {}", owner);
 
-        while let Some(result) = stream.next().await {
-            let row = result?;
-            let label: &str = row.get(2);
+        let conn = self.read_conn().await?;
+        let select_stories = conn.statement(Stmt::SelectOwnedStories).await?;
 
-            if label == "current" {
-                stories.push(Story::new(row.get(0), row.get(1)));
-            } else if label == "prev" {
-                prev_pid = row.get(0);
-            } else if label == "next" {
-                next_pid = row.get(0);
-            } else {
-                tracing::warn!("unknown page label: {}", label);
-            }
-        }
+        let rows = select_stories
+            .query(&conn.inner, &[&page_id, &owner])
+            .await?;
+        Ok(to_page(rows, to_story))
+    }
 
-        Ok((prev_pid, next_pid, stories))
+    /// Select a page of the stories shared with a member, with their role on each.
+    pub async fn select_shared_stories(
+        &self,
+        page_id: i32,
+        member: &str,
+    ) -> Result<(i32, i32, Vec<SharedStory>)> {
+        tracing::debug!("select_shared_stories: {}", member);
+
+        let conn = self.read_conn().await?;
+        let select_stories = conn.statement(Stmt::SelectSharedStories).await?;
+
+        let rows = select_stories
+            .query(&conn.inner, &[&page_id, &member])
+            .await?;
+        Ok(to_page(rows, |row| {
+            let role: &str = row.get(4);
+            SharedStory {
+                story: to_story(row),
+                // Constrained by the table, so unknown roles get the least access
+                role: Role::from_str(role).unwrap_or(Role::Viewer),
+            }
+        }))
     }
 
     /// Insert a new story
-    pub async fn insert_story(&self, name: String) -> Result<Story> {
-        tracing::debug!("insert_story: {}", name);
+    pub async fn insert_story(&self, name: String, owner: String) -> Result<Story> {
+        let story = match self.batcher.as_ref() {
+            Some(batcher) => batcher.insert_story(name, owner).await?,
+            None => insert(&*self.pool.get().await?, name, owner).await?,
+        };
+        self.invalidate(&[Invalidation::FirstPage]);
+        Ok(story)
+    }
+
+    /// Delete a story and all of its tasks.
+    pub async fn delete_story(&self, id: i32) -> Result<u64> {
+        let uow = self.begin().await?;
+        let num_rows = uow.delete_story(id).await?;
+        uow.commit().await?;
+        Ok(num_rows)
+    }
 
-        let mut conn = self.pool.get().await?;
-        let insert_story = conn.prepare_cache(sql::stories::INSERT).await?;
+    /// Update a story the caller may edit, checking their role in the update itself.
+    pub async fn update_story(
+        &self,
+        id: i32,
+        name: String,
+        principal: &Principal,
+    ) -> Result<Story> {
+        let conn = self.pool.get().await?;
+        let result = update(&conn, id, name, principal.owner_filter()).await;
+        drop(conn);
+        let story = self.or_denied(result, id, principal, Role::Editor).await?;
+        self.invalidate(&[Invalidation::Story(id), Invalidation::FirstPage]);
+        Ok(story)
+    }
+}
 
-        let stream = conn.query_raw(&insert_story, &[&name]).await?;
-        pin!(stream);
+/// Select a story by id
+pub(super) async fn fetch(conn: &PgConn, id: i32) -> Result<Story> {
+    tracing::debug!("select_story: {}", id);
 
-        if let Some(result) = stream.next().await {
-            let row = result?;
-            Ok(Story::new(row.get(0), name))
-        } else {
-            Err(Error::internal(format!("failed to insert story: {}", name)))
-        }
+    let select_story = conn.statement(Stmt::FetchStory).await?;
+
+    if let Some(row) = select_story.query_opt(&conn.inner, &[&id]).await? {
+        Ok(Story::new(row.get(0), row.get(1), row.get(2)))
+    } else {
+        Err(Error::not_found(format!("story not found: {}", id)))
     }
+}
 
-    /// Delete a story.
-    pub async fn delete_story(&self, id: i32) -> Result<u64> {
-        tracing::debug!("delete_story: {}", id);
+/// Insert a new story
+pub(super) async fn insert(conn: &PgConn, name: String, owner: String) -> Result<Story> {
+    tracing::debug!("insert_story: {}", name);
 
-        let mut conn = self.pool.get().await?;
-        let delete_tasks = conn.prepare_cache(sql::tasks::DELETE_BY_STORY).await?;
-        let delete_story = conn.prepare_cache(sql::stories::DELETE).await?;
+    let insert_story = conn.statement(Stmt::InsertStory).await?;
 
-        let tx = conn.transaction().await?;
+    if let Some(row) = insert_story
+        .query_opt(&conn.inner, &[&name, &owner])
+        .await?
+    {
+        Ok(Story::new(row.get(0), name, Some(owner)))
+    } else {
+        Err(Error::internal(format!("failed to insert story: {}", name)))
+    }
+}
 
-        // Delete all tasks for the story
-        let num_tasks = tx
-            .execute_raw(&delete_tasks, &[&id])
-            .await
-            .map_err(Error::from)?;
+/// Delete a story and its tasks; must run inside a transaction.
+pub(super) async fn delete(conn: &PgConn, id: i32) -> Result<u64> {
+    tracing::debug!("delete_story: {}", id);
 
-        // Delete the story
-        let num_stories = tx
-            .execute_raw(&delete_story, &[&id])
-            .await
-            .map_err(Error::from)?;
+    let delete_tasks = conn.statement(Stmt::DeleteTasksByStory).await?;
+    let delete_story = conn.statement(Stmt::DeleteStory).await?;
 
-        tx.commit().await?;
+    // Delete all tasks for the story
+    let num_tasks = delete_tasks.execute(&conn.inner, &[&id]).await?;
 
-        Ok(num_tasks + num_stories)
-    }
+    // Delete the story
+    let num_stories = delete_story.execute(&conn.inner, &[&id]).await?;
+
+    Ok(num_tasks + num_stories)
+}
 
-    /// Update a story.
-    pub async fn update_story(&self, id: i32, name: String) -> Result<Story> {
-        tracing::debug!("update_story: {}, {}", id, name);
+/// Update a story; with an editor filter, stories the caller can't edit are not found.
+pub(super) async fn update(
+    conn: &PgConn,
+    id: i32,
+    name: String,
+    editor: Option<&str>,
+) -> Result<Story> {
+    tracing::debug!("update_story: {}, {}", id, name);
+
+    let update_story = conn.statement(Stmt::UpdateStory).await?;
+
+    match update_story
+        .query_opt(&conn.inner, &[&name, &id, &editor])
+        .await?
+    {
+        Some(row) => Ok(Story::new(id, name, row.get(0))),
+        None => Err(Error::not_found(format!("story not found: {}", id))),
+    }
+}
 
-        let mut conn = self.pool.get().await?;
-        let update_story = conn.prepare_cache(sql::stories::UPDATE).await?;
+/// Map a story row.
+fn to_story(row: &Row) -> Story {
+    Story::new(row.get(0), row.get(1), row.get(2))
+}
 
-        let num_rows = conn.execute(&update_story, &[&name, &id]).await?;
-        if num_rows > 0 {
-            Ok(Story::new(id, name))
+/// Map labelled page rows to the current page and its neighbouring page cursors.
+fn to_page<T>(rows: Vec<Row>, item: impl Fn(&Row) -> T) -> (i32, i32, Vec<T>) {
+    let mut prev_pid: i32 = 0;
+    let mut next_pid: i32 = 0;
+    let mut stories = Vec::with_capacity(PAGE_SIZE);
+
+    for row in rows {
+        let label: &str = row.get(3);
+
+        if label == "current" {
+            stories.push(item(&row));
+        } else if label == "prev" {
+            prev_pid = row.get(0);
+        } else if label == "next" {
+            next_pid = row.get(0);
         } else {
-            Err(Error::internal("unable to update story".into()))
+            tracing::warn!("unknown page label: {}", label);
         }
     }
+
+    (prev_pid, next_pid, stories)
 }
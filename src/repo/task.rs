@@ -1,13 +1,14 @@
 use std::str::FromStr;
 
 use crate::{
-    domain::{Status, Task},
-    repo::Repo,
+    db::pool::connection::PgConn,
+    domain::{Principal, Role, Status, Task},
+    repo::{cache::Invalidation, Repo},
     Error, Result,
 };
-use tokio_postgres::Row;
+use tokio_postgres::{error::SqlState, Row};
 
-use crate::db::sql;
+use crate::db::sql::Stmt;
 
 /// Row mapper for the task domain object.
 impl From<&Row> for Task {
@@ -21,28 +22,32 @@ impl From<&Row> for Task {
 impl Repo {
     /// Select a task by id
     pub async fn select_task(&self, id: i32) -> Result<Task> {
-        tracing::debug!("select_task: {}", id);
-
-        let mut conn = self.pool.get().await?;
-        let select_task = conn.prepare_cache(sql::tasks::FETCH).await?;
-        let result = conn.query_one(&select_task, &[&id]).await;
-
-        if let Ok(row) = result {
-            Ok(Task::from(&row))
-        } else {
-            Err(Error::not_found(format!("task not found: {}", id)))
+        if let Some(task) = self.read_cache().and_then(|c| c.task(id)) {
+            return Ok(task);
         }
+        let ticket = self.cache.as_ref().map(|c| c.task_ticket(id));
+        let task = self.select_task_uncached(id).await?;
+        if let (Some(cache), Some(ticket)) = (self.cache.as_ref(), ticket) {
+            cache.put_task(ticket, &task);
+        }
+        Ok(task)
+    }
+
+    /// Select a task from the primary, bypassing the cache; for read-modify-write updates.
+    pub async fn select_task_uncached(&self, id: i32) -> Result<Task> {
+        let conn = self.pool.get().await?;
+        fetch(&conn, id).await
     }
 
     /// Select a page of tasks for a story.
     pub async fn select_tasks(&self, story_id: i32, page_id: i32) -> Result<Vec<Task>> {
         tracing::debug!("select_tasks: {}", story_id);
 
-        let mut conn = self.pool.get().await?;
-        let select_tasks = conn.prepare_cache(sql::tasks::SELECT).await?;
+        let conn = self.read_conn().await?;
+        let select_tasks = conn.statement(Stmt::SelectTasks).await?;
 
-        let tasks: Vec<_> = conn
-            .query(&select_tasks, &[&story_id, &page_id])
+        let tasks: Vec<_> = select_tasks
+            .query(&conn.inner, &[&story_id, &page_id])
             .await?
             .iter()
             .map(Task::from)
@@ -51,47 +56,272 @@ impl Repo {
         Ok(tasks)
     }
 
-    /// Insert a new task
-    pub async fn insert_task(&self, story_id: i32, name: String) -> Result<Task> {
-        tracing::debug!("insert_task: {}, {}", story_id, name);
+    /// Insert a new task in a story the caller may edit, checking their role in the insert.
+    pub async fn insert_task(
+        &self,
+        story_id: i32,
+        name: String,
+        principal: &Principal,
+    ) -> Result<Task> {
+        let editor = principal.owner_filter();
+        let result = match self.batcher.as_ref() {
+            Some(batcher) => {
+                let editor = editor.map(String::from);
+                batcher.insert_task(story_id, name, editor).await
+            }
+            None => insert(&*self.pool.get().await?, story_id, name, editor).await,
+        };
+        self.or_denied(result, story_id, principal, Role::Editor)
+            .await
+    }
+
+    /// Insert many tasks for a story the caller may edit, in one transaction with a single
+    /// multi-row insert.
+    pub async fn insert_tasks(
+        &self,
+        story_id: i32,
+        names: Vec<String>,
+        principal: &Principal,
+    ) -> Result<Vec<Task>> {
+        let uow = self.begin().await?;
+        let result = uow
+            .insert_tasks(story_id, names, principal.owner_filter())
+            .await;
+        let tasks = match result {
+            Ok(tasks) => tasks,
+            Err(err) => {
+                uow.rollback().await?;
+                return self
+                    .or_denied(Err(err), story_id, principal, Role::Editor)
+                    .await;
+            }
+        };
+        uow.commit().await?;
+        Ok(tasks)
+    }
+
+    /// Delete a task.
+    pub async fn delete_task(&self, id: i32) -> Result<u64> {
+        let conn = self.pool.get().await?;
+        let num_rows = delete(&conn, id).await?;
+        self.invalidate(&[Invalidation::Task(id)]);
+        Ok(num_rows)
+    }
+
+    /// Set the status of many tasks by id, returning the number of tasks updated.
+    /// When an owner is given, tasks in other owners' stories are left alone.
+    pub async fn update_task_statuses(
+        &self,
+        ids: Vec<i32>,
+        status: Status,
+        owner: Option<&str>,
+    ) -> Result<u64> {
+        tracing::debug!("update_task_statuses: {:?}, {:?}", ids, status);
 
-        let mut conn = self.pool.get().await?;
-        let insert_task = conn.prepare_cache(sql::tasks::INSERT).await?;
+        let conn = self.pool.get().await?;
+        let update_statuses = conn.statement(Stmt::UpdateTaskStatuses).await?;
 
-        let status: Status = Default::default();
         let status_string = status.to_string();
-        let row = conn
-            .query_one(&insert_task, &[&story_id, &name, &status_string])
+        let count = update_statuses
+            .execute(&conn.inner, &[&status_string, &ids, &owner])
             .await?;
 
-        Ok(Task::new(row.get(0), story_id, name, status))
+        let invalidations: Vec<_> = ids.into_iter().map(Invalidation::Task).collect();
+        self.invalidate(&invalidations);
+        Ok(count)
     }
 
-    /// Delete a task.
-    pub async fn delete_task(&self, id: i32) -> Result<u64> {
-        tracing::debug!("delete_task: {}", id);
+    /// Set the status of all tasks in a story the caller may edit, optionally only those with
+    /// a given status. Their role is checked in the update.
+    pub async fn update_story_task_statuses(
+        &self,
+        story_id: i32,
+        status: Status,
+        filter: Option<Status>,
+        principal: &Principal,
+    ) -> Result<u64> {
+        tracing::debug!(
+            "update_story_task_statuses: {}, {:?}, {:?}",
+            story_id,
+            status,
+            filter
+        );
 
-        let mut conn = self.pool.get().await?;
-        let delete_task = conn.prepare_cache(sql::tasks::DELETE).await?;
+        let conn = self.pool.get().await?;
+        let update_statuses = conn.statement(Stmt::UpdateStoryTaskStatuses).await?;
 
-        conn.execute(&delete_task, &[&id])
-            .await
-            .map_err(Error::from)
+        let status_string = status.to_string();
+        let filter_string = filter.map(|s| s.to_string());
+        let editor = principal.owner_filter();
+        let row = update_statuses
+            .query_opt(
+                &conn.inner,
+                &[&status_string, &story_id, &filter_string, &editor],
+            )
+            .await?;
+        drop(conn);
+
+        let count = self
+            .or_denied(
+                allowed_count(row, story_id),
+                story_id,
+                principal,
+                Role::Editor,
+            )
+            .await?;
+        self.invalidate(&[Invalidation::StoryTasks(story_id)]);
+        Ok(count)
+    }
+
+    /// Delete all tasks in a story the caller may edit, optionally only those with a given
+    /// status. Their role is checked in the delete.
+    pub async fn delete_story_tasks(
+        &self,
+        story_id: i32,
+        filter: Option<Status>,
+        principal: &Principal,
+    ) -> Result<u64> {
+        tracing::debug!("delete_story_tasks: {}, {:?}", story_id, filter);
+
+        let conn = self.pool.get().await?;
+        let delete_tasks = conn.statement(Stmt::DeleteTasksByStatus).await?;
+
+        let filter_string = filter.map(|s| s.to_string());
+        let editor = principal.owner_filter();
+        let row = delete_tasks
+            .query_opt(&conn.inner, &[&story_id, &filter_string, &editor])
+            .await?;
+        drop(conn);
+
+        let count = self
+            .or_denied(
+                allowed_count(row, story_id),
+                story_id,
+                principal,
+                Role::Editor,
+            )
+            .await?;
+        self.invalidate(&[Invalidation::StoryTasks(story_id)]);
+        Ok(count)
     }
 
     /// Update task name and status.
     pub async fn update_task(&self, id: i32, name: String, status: Status) -> Result<Task> {
-        tracing::debug!("update_task: {}, {}, {:?}", id, name, status);
+        let conn = self.pool.get().await?;
+        let task = update(&conn, id, name, status).await?;
+        self.invalidate(&[Invalidation::Task(id)]);
+        Ok(task)
+    }
+}
 
-        let mut conn = self.pool.get().await?;
-        let update_task = conn.prepare_cache(sql::tasks::UPDATE).await?;
+/// Select a task by id
+pub(super) async fn fetch(conn: &PgConn, id: i32) -> Result<Task> {
+    tracing::debug!("select_task: {}", id);
 
-        let status_string = status.to_string();
-        let row = conn
-            .query_one(&update_task, &[&name, &status_string, &id])
-            .await?;
+    let select_task = conn.statement(Stmt::FetchTask).await?;
+    let result = select_task.query_opt(&conn.inner, &[&id]).await;
 
-        // Note: only need story_id from db
-        Ok(Task::new(id, row.get(0), name, status))
+    if let Ok(Some(row)) = result {
+        Ok(Task::from(&row))
+    } else {
+        Err(Error::not_found(format!("task not found: {}", id)))
     }
 }
+
+/// Map a row of whether a story-wide write was allowed and how many rows it changed;
+/// not allowed means not found.
+fn allowed_count(row: Option<Row>, story_id: i32) -> Result<u64> {
+    match row {
+        Some(row) if row.get::<_, bool>(0) => Ok(row.get::<_, i64>(1) as u64),
+        _ => Err(Error::not_found(format!("story not found: {}", story_id))),
+    }
+}
+
+/// Insert a new task; the story existence and editor checks are part of the insert.
+pub(super) async fn insert(
+    conn: &PgConn,
+    story_id: i32,
+    name: String,
+    editor: Option<&str>,
+) -> Result<Task> {
+    tracing::debug!("insert_task: {}, {}", story_id, name);
+
+    let insert_task = conn.statement(Stmt::InsertTask).await?;
+
+    let status: Status = Default::default();
+    let status_string = status.to_string();
+    let story_not_found = || Error::not_found(format!("story not found: {}", story_id));
+    // A foreign key violation means the story was deleted concurrently
+    let row = insert_task
+        .try_query_opt(&conn.inner, &[&story_id, &name, &status_string, &editor])
+        .await
+        .map_err(|err| match err.code() {
+            Some(&SqlState::FOREIGN_KEY_VIOLATION) => story_not_found(),
+            _ => err.into(),
+        })?
+        .ok_or_else(story_not_found)?;
+
+    Ok(Task::new(row.get(0), story_id, name, status))
+}
+
+/// Insert many tasks for a story with a single multi-row insert; must run inside a transaction.
+pub(super) async fn insert_many(
+    conn: &PgConn,
+    story_id: i32,
+    names: Vec<String>,
+    editor: Option<&str>,
+) -> Result<Vec<Task>> {
+    tracing::debug!("insert_tasks: {}, {}", story_id, names.len());
+
+    let lock_story = conn.statement(Stmt::LockStory).await?;
+    let insert_tasks = conn.statement(Stmt::InsertTasks).await?;
+
+    // Make sure the story exists and the caller may edit it, and stays that way until commit
+    if lock_story
+        .query_opt(&conn.inner, &[&story_id, &editor])
+        .await?
+        .is_none()
+    {
+        return Err(Error::not_found(format!("story not found: {}", story_id)));
+    }
+
+    let status: Status = Default::default();
+    let status_string = status.to_string();
+    let rows = insert_tasks
+        .query(&conn.inner, &[&story_id, &names, &status_string])
+        .await?;
+
+    // Ordered by input position, so ids line up with names
+    let tasks = rows
+        .iter()
+        .zip(names)
+        .map(|(row, name)| Task::new(row.get(0), story_id, name, status))
+        .collect();
+
+    Ok(tasks)
+}
+
+/// Delete a task.
+pub(super) async fn delete(conn: &PgConn, id: i32) -> Result<u64> {
+    tracing::debug!("delete_task: {}", id);
+
+    let delete_task = conn.statement(Stmt::DeleteTask).await?;
+
+    delete_task.execute(&conn.inner, &[&id]).await
+}
+
+/// Update task name and status.
+pub(super) async fn update(conn: &PgConn, id: i32, name: String, status: Status) -> Result<Task> {
+    tracing::debug!("update_task: {}, {}, {:?}", id, name, status);
+
+    let update_task = conn.statement(Stmt::UpdateTask).await?;
+
+    let status_string = status.to_string();
+    let row = update_task
+        .query_opt(&conn.inner, &[&name, &status_string, &id])
+        .await?
+        .ok_or_else(|| Error::not_found(format!("task not found: {}", id)))?;
+
+    // Note: only need story_id from db
+    Ok(Task::new(id, row.get(0), name, status))
+}
@@ -0,0 +1,165 @@
+use crate::{
+    db::pool::PgPooledConn,
+    domain::{Role, Status, Story, Task},
+    repo::{
+        cache::{Invalidation, ReadCache},
+        member, story, task, Repo,
+    },
+    Error, Result,
+};
+use std::sync::{Arc, Mutex};
+
+/// A database transaction spanning several repo operations.
+/// Rolled back unless committed; dropping it unfinished discards the connection, so it can
+/// never go back to the pool inside the transaction.
+pub struct UnitOfWork {
+    conn: Option<PgPooledConn>,
+    cache: Option<Arc<ReadCache>>,
+    invalidations: Mutex<Vec<Invalidation>>,
+}
+
+impl Repo {
+    /// Begin a unit of work on an exclusive connection.
+    pub async fn begin(&self) -> Result<UnitOfWork> {
+        let conn = self.pool.get_exclusive().await?;
+        if let Err(err) = conn.inner.batch_execute("begin").await {
+            conn.discard();
+            return Err(err.into());
+        }
+        Ok(UnitOfWork {
+            conn: Some(conn),
+            cache: self.cache.clone(),
+            invalidations: Mutex::new(Vec::new()),
+        })
+    }
+}
+
+impl UnitOfWork {
+    fn conn(&self) -> Result<&PgPooledConn> {
+        self.conn
+            .as_ref()
+            .ok_or_else(|| Error::internal("unit of work already finished".into()))
+    }
+
+    /// Record cached entries to drop once the changes are committed.
+    fn invalidate(&self, invalidations: &[Invalidation]) {
+        if self.cache.is_some() {
+            self.invalidations.lock().unwrap().extend(invalidations);
+        }
+    }
+
+    /// Select a story by id
+    pub async fn select_story(&self, id: i32) -> Result<Story> {
+        story::fetch(self.conn()?, id).await
+    }
+
+    /// Select a member's role on a story, if any.
+    pub async fn select_member_role(&self, story_id: i32, member: &str) -> Result<Option<Role>> {
+        member::fetch_role(self.conn()?, story_id, member).await
+    }
+
+    /// Insert a new story
+    pub async fn insert_story(&self, name: String, owner: String) -> Result<Story> {
+        let story = story::insert(self.conn()?, name, owner).await?;
+        self.invalidate(&[Invalidation::FirstPage]);
+        Ok(story)
+    }
+
+    /// Update a story.
+    pub async fn update_story(&self, id: i32, name: String, editor: Option<&str>) -> Result<Story> {
+        let story = story::update(self.conn()?, id, name, editor).await?;
+        self.invalidate(&[Invalidation::Story(id), Invalidation::FirstPage]);
+        Ok(story)
+    }
+
+    /// Delete a story and all of its tasks.
+    pub async fn delete_story(&self, id: i32) -> Result<u64> {
+        let num_rows = story::delete(self.conn()?, id).await?;
+        self.invalidate(&[
+            Invalidation::Story(id),
+            Invalidation::StoryTasks(id),
+            Invalidation::FirstPage,
+        ]);
+        Ok(num_rows)
+    }
+
+    /// Select a task by id
+    pub async fn select_task(&self, id: i32) -> Result<Task> {
+        task::fetch(self.conn()?, id).await
+    }
+
+    /// Insert a new task
+    pub async fn insert_task(
+        &self,
+        story_id: i32,
+        name: String,
+        editor: Option<&str>,
+    ) -> Result<Task> {
+        task::insert(self.conn()?, story_id, name, editor).await
+    }
+
+    /// Insert many tasks for a story with a single multi-row insert.
+    pub async fn insert_tasks(
+        &self,
+        story_id: i32,
+        names: Vec<String>,
+        editor: Option<&str>,
+    ) -> Result<Vec<Task>> {
+        task::insert_many(self.conn()?, story_id, names, editor).await
+    }
+
+    /// Update task name and status.
+    pub async fn update_task(&self, id: i32, name: String, status: Status) -> Result<Task> {
+        let task = task::update(self.conn()?, id, name, status).await?;
+        self.invalidate(&[Invalidation::Task(id)]);
+        Ok(task)
+    }
+
+    /// Delete a task.
+    pub async fn delete_task(&self, id: i32) -> Result<u64> {
+        let num_rows = task::delete(self.conn()?, id).await?;
+        self.invalidate(&[Invalidation::Task(id)]);
+        Ok(num_rows)
+    }
+
+    /// Commit all changes made in this unit of work.
+    pub async fn commit(mut self) -> Result<()> {
+        let conn = self.conn.take();
+        finish(conn, "commit").await?;
+        if let Some(cache) = self.cache.as_ref() {
+            cache.invalidate(&self.invalidations.lock().unwrap());
+        }
+        Ok(())
+    }
+
+    /// Discard all changes made in this unit of work.
+    pub async fn rollback(mut self) -> Result<()> {
+        let conn = self.conn.take();
+        finish(conn, "rollback").await
+    }
+}
+
+/// End the transaction; if that fails, its state is unknown, so the connection is discarded.
+async fn finish(conn: Option<PgPooledConn>, sql: &str) -> Result<()> {
+    let Some(conn) = conn else {
+        return Err(Error::internal("unit of work already finished".into()));
+    };
+    match conn.inner.batch_execute(sql).await {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            conn.discard();
+            Err(err.into())
+        }
+    }
+}
+
+/// Discard the connection of an unfinished unit of work, e.g. when the request is cancelled
+/// mid-transaction, rather than relying on a background rollback before it is reused.
+impl Drop for UnitOfWork {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            tracing::warn!("unit of work dropped without commit or rollback");
+            conn.discard();
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{
+    db::sql::Stmt,
+    domain::{Principal, Scope, User},
+    repo::{
+        token::{generate_token, hash_token},
+        Repo,
+    },
+    Error, Result,
+};
+use std::str::FromStr;
+use std::time::Duration;
+
+impl Repo {
+    /// Insert a new user with an already hashed password.
+    pub async fn insert_user(&self, username: String, password_hash: String) -> Result<User> {
+        tracing::debug!("insert_user: {}", username);
+
+        let conn = self.pool.get().await?;
+        let insert_user = conn.statement(Stmt::InsertUser).await?;
+
+        match insert_user
+            .query_opt(&conn.inner, &[&username, &password_hash])
+            .await?
+        {
+            Some(row) => Ok(User::new(row.get(0), row.get(1))),
+            None => Err(Error::conflict(format!("username is taken: {}", username))),
+        }
+    }
+
+    /// Select a user and their password hash by username.
+    pub async fn select_user_credentials(&self, username: &str) -> Result<Option<(User, String)>> {
+        let conn = self.pool.get().await?;
+        let fetch_user = conn.statement(Stmt::FetchUser).await?;
+
+        let row = fetch_user.query_opt(&conn.inner, &[&username]).await?;
+        Ok(row.map(|row| (User::new(row.get(0), row.get(1)), row.get(2))))
+    }
+
+    /// Start a session for a user, returning its token.
+    /// Only a hash of the token is stored. The user's expired sessions are pruned.
+    pub async fn insert_session(&self, user_id: i32, ttl: Duration) -> Result<String> {
+        let conn = self.pool.get().await?;
+        let delete_expired = conn.statement(Stmt::DeleteExpiredSessions).await?;
+        let insert_session = conn.statement(Stmt::InsertSession).await?;
+
+        delete_expired.execute(&conn.inner, &[&user_id]).await?;
+        let token = generate_token();
+        insert_session
+            .execute(
+                &conn.inner,
+                &[&hash_token(&token), &user_id, &ttl.as_secs_f64()],
+            )
+            .await?;
+        Ok(token)
+    }
+
+    /// Look up the caller for a session token; `None` when unknown or expired.
+    /// Always reads from the primary so logouts take effect immediately.
+    pub async fn authenticate_session(&self, token: &str) -> Result<Option<Principal>> {
+        let conn = self.pool.get().await?;
+        let fetch_session = conn.statement(Stmt::FetchSession).await?;
+
+        let row = fetch_session
+            .query_opt(&conn.inner, &[&hash_token(token)])
+            .await?;
+        Ok(row.map(|row| {
+            let scope: &str = row.get(1);
+            Principal {
+                subject: User::subject(row.get(0)),
+                // Constrained by the table, so unknown scopes get the least access
+                scope: Scope::from_str(scope).unwrap_or(Scope::Read),
+            }
+        }))
+    }
+
+    /// Replace a live session's token with a new one and extend its expiry.
+    /// Returns the new token, or `None` when the session is unknown or expired.
+    pub async fn refresh_session(&self, token: &str, ttl: Duration) -> Result<Option<String>> {
+        let conn = self.pool.get().await?;
+        let refresh_session = conn.statement(Stmt::RefreshSession).await?;
+
+        let new_token = generate_token();
+        let row = refresh_session
+            .query_opt(
+                &conn.inner,
+                &[
+                    &hash_token(token),
+                    &hash_token(&new_token),
+                    &ttl.as_secs_f64(),
+                ],
+            )
+            .await?;
+        Ok(row.map(|_| new_token))
+    }
+
+    /// End a session.
+    pub async fn delete_session(&self, token: &str) -> Result<u64> {
+        let conn = self.pool.get().await?;
+        let delete_session = conn.statement(Stmt::DeleteSession).await?;
+        delete_session
+            .execute(&conn.inner, &[&hash_token(token)])
+            .await
+    }
+}
@@ -0,0 +1,228 @@
+use crate::{
+    config::Config,
+    db::{pool::PgPoolBuilder, sql},
+    domain::Status,
+    Result,
+};
+use futures::pin_mut;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::time::Instant;
+use tokio_postgres::{binary_copy::BinaryCopyInWriter, types::Type};
+
+const VERBS: &[&str] = &[
+    "Build",
+    "Fix",
+    "Refactor",
+    "Document",
+    "Review",
+    "Migrate",
+    "Test",
+    "Deploy",
+    "Design",
+    "Plan",
+    "Clean up",
+    "Upgrade",
+    "Investigate",
+    "Benchmark",
+    "Automate",
+    "Remove",
+];
+
+const SUBJECTS: &[&str] = &[
+    "billing",
+    "checkout",
+    "search",
+    "login",
+    "signup",
+    "onboarding",
+    "reporting",
+    "settings",
+    "notifications",
+    "the api",
+    "the dashboard",
+    "invoices",
+    "exports",
+    "the mobile app",
+    "permissions",
+    "the scheduler",
+    "caching",
+    "logging",
+    "metrics",
+    "the database",
+];
+
+const DETAILS: &[&str] = &[
+    "flow",
+    "page",
+    "service",
+    "pipeline",
+    "endpoint",
+    "docs",
+    "tests",
+    "config",
+    "schema",
+    "ui",
+    "jobs",
+    "alerts",
+    "queries",
+    "migration",
+];
+
+/// Options for the seed subcommand.
+#[derive(Debug)]
+pub struct SeedOptions {
+    pub stories: u64,
+    pub tasks_per_story: u32,
+    pub seed: u64,
+    pub batch_size: usize,
+    pub owner: Option<String>,
+}
+
+impl Default for SeedOptions {
+    fn default() -> Self {
+        Self {
+            stories: 10_000,
+            tasks_per_story: 10,
+            seed: 0,
+            batch_size: 10_000,
+            owner: None,
+        }
+    }
+}
+
+impl SeedOptions {
+    /// Parse options from `--name value` command line arguments.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut opts = Self::default();
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{}: missing value", flag))?;
+            let invalid = || format!("{}: invalid value: {}", flag, value);
+            match flag.as_str() {
+                "--stories" => opts.stories = value.parse().map_err(|_| invalid())?,
+                "--tasks-per-story" => {
+                    opts.tasks_per_story = value.parse().map_err(|_| invalid())?
+                }
+                "--seed" => opts.seed = value.parse().map_err(|_| invalid())?,
+                "--batch-size" => opts.batch_size = value.parse().map_err(|_| invalid())?,
+                "--owner" if !value.trim().is_empty() => opts.owner = Some(value.trim().into()),
+                "--owner" => return Err(invalid()),
+                _ => return Err(format!("unknown flag: {}", flag)),
+            }
+        }
+        opts.batch_size = opts.batch_size.max(1);
+        Ok(opts)
+    }
+}
+
+/// Generate a plausible story or task name.
+fn name<R: Rng>(rng: &mut R) -> String {
+    format!(
+        "{} {} {}",
+        VERBS.choose(rng).unwrap_or(&"Build"),
+        SUBJECTS.choose(rng).unwrap_or(&"billing"),
+        DETAILS.choose(rng).unwrap_or(&"flow"),
+    )
+}
+
+/// Bulk load generated stories and tasks with binary COPY.
+/// The same seed value always produces the same data. Stories have no owner unless one is given.
+pub async fn run(config: &Config, opts: SeedOptions) -> Result<()> {
+    tracing::info!("seeding: {:?}", opts);
+
+    let pool = PgPoolBuilder::build(config).await?;
+    let mut conn = pool.get_exclusive().await?;
+    let mut rng = ChaCha8Rng::seed_from_u64(opts.seed);
+    let start = Instant::now();
+    let (mut num_stories, mut num_tasks) = (0u64, 0u64);
+
+    while num_stories < opts.stories {
+        let count = (opts.stories - num_stories).min(opts.batch_size as u64) as usize;
+        let names: Vec<String> = (0..count).map(|_| name(&mut rng)).collect();
+
+        let tx = conn.transaction().await?;
+
+        // Stories get identity ids; hold a lock so the new ids can be read back in order
+        tx.batch_execute(&sql::for_config(sql::stories::LOCK, config))
+            .await?;
+        let max_id: i32 = tx
+            .query_one(&*sql::for_config(sql::stories::MAX_ID, config), &[])
+            .await?
+            .get(0);
+        match opts.owner.as_ref() {
+            Some(owner) => {
+                let sink = tx
+                    .copy_in(&*sql::for_config(sql::stories::COPY_IN_OWNED, config))
+                    .await?;
+                let writer = BinaryCopyInWriter::new(sink, &[Type::TEXT, Type::TEXT]);
+                pin_mut!(writer);
+                for name in &names {
+                    writer.as_mut().write(&[name, owner]).await?;
+                }
+                writer.finish().await?;
+            }
+            None => {
+                let sink = tx
+                    .copy_in(&*sql::for_config(sql::stories::COPY_IN, config))
+                    .await?;
+                let writer = BinaryCopyInWriter::new(sink, &[Type::TEXT]);
+                pin_mut!(writer);
+                for name in &names {
+                    writer.as_mut().write(&[name]).await?;
+                }
+                writer.finish().await?;
+            }
+        }
+        let ids: Vec<i32> = tx
+            .query(
+                &*sql::for_config(sql::stories::SELECT_IDS_AFTER, config),
+                &[&max_id],
+            )
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        // Each story gets between zero and twice the average number of tasks,
+        // with its own share of completed tasks
+        let sink = tx
+            .copy_in(&*sql::for_config(sql::tasks::COPY_IN, config))
+            .await?;
+        let writer = BinaryCopyInWriter::new(sink, &[Type::INT4, Type::TEXT, Type::TEXT]);
+        pin_mut!(writer);
+        for story_id in &ids {
+            let tasks = rng.gen_range(0..=2 * opts.tasks_per_story);
+            let done = rng.gen::<f64>();
+            for _ in 0..tasks {
+                let status = if rng.gen_bool(done) {
+                    Status::Complete
+                } else {
+                    Status::Incomplete
+                };
+                let task_name = name(&mut rng);
+                writer
+                    .as_mut()
+                    .write(&[story_id, &task_name, &status.to_string()])
+                    .await?;
+            }
+        }
+        num_tasks += writer.finish().await?;
+
+        tx.commit().await?;
+
+        num_stories += ids.len() as u64;
+        tracing::info!("seeded {} stories, {} tasks", num_stories, num_tasks);
+    }
+
+    tracing::info!(
+        "seeded {} stories and {} tasks in {:?}",
+        num_stories,
+        num_tasks,
+        start.elapsed()
+    );
+
+    Ok(())
+}
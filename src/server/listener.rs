@@ -0,0 +1,192 @@
+use crate::config::Config;
+use socket2::{SockRef, Socket, Type};
+use std::fs::{self, Permissions};
+use std::io;
+use std::net::SocketAddr;
+use std::os::fd::{BorrowedFd, FromRawFd, RawFd};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+
+/// The first file descriptor passed by systemd socket activation.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// A listening socket: tcp or unix, bound here or inherited from systemd.
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// Use a socket passed by systemd if there is one, else a unix socket path if set, else tcp.
+    pub async fn bind(config: &Config) -> io::Result<Self> {
+        if let Some(fd) = systemd_fd() {
+            return Self::from_fd(fd);
+        }
+        match config.listen_unix_path.as_ref() {
+            Some(path) => Self::bind_unix(path, config.listen_unix_mode),
+            None => Ok(Self::Tcp(config.tcp_listener().await)),
+        }
+    }
+
+    /// Bind a unix socket, replacing a stale socket file left by a previous run.
+    fn bind_unix(path: &str, mode: u32) -> io::Result<Self> {
+        if let Ok(meta) = fs::symlink_metadata(path) {
+            if !meta.file_type().is_socket() {
+                let msg = format!("{} exists and is not a socket", path);
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, msg));
+            }
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, Permissions::from_mode(mode))?;
+        Ok(Self::Unix(listener))
+    }
+
+    /// Wrap an inherited listening socket, checking whether it is tcp or unix.
+    fn from_fd(fd: RawFd) -> io::Result<Self> {
+        // Safety: systemd passed the descriptor open; if it didn't, getsockopt fails with EBADF
+        let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+        check_listening(SockRef::from(&borrowed))?;
+        // Safety: systemd hands this process ownership of the descriptor
+        let socket = unsafe { Socket::from_raw_fd(fd) };
+        socket.set_nonblocking(true)?;
+        if socket.local_addr()?.as_socket().is_some() {
+            Ok(Self::Tcp(TcpListener::from_std(socket.into())?))
+        } else {
+            Ok(Self::Unix(UnixListener::from_std(socket.into())?))
+        }
+    }
+
+    /// Describe the bound address for logs.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Tcp(l) => l
+                .local_addr()
+                .map(|a| a.to_string())
+                .unwrap_or_else(|_| "tcp".into()),
+            Self::Unix(l) => l
+                .local_addr()
+                .ok()
+                .and_then(|a| a.as_pathname().map(|p| format!("unix:{}", p.display())))
+                .unwrap_or_else(|| "unix".into()),
+        }
+    }
+
+    /// Accept the next connection, with the peer address for tcp connections.
+    pub async fn accept(&self) -> io::Result<(Stream, Option<SocketAddr>)> {
+        match self {
+            Self::Tcp(l) => {
+                let (stream, addr) = l.accept().await?;
+                if let Err(err) = stream.set_nodelay(true) {
+                    tracing::warn!("failed to set TCP_NODELAY: {}", err);
+                }
+                Ok((Stream::Tcp(stream), Some(addr)))
+            }
+            Self::Unix(l) => Ok((Stream::Unix(l.accept().await?.0), None)),
+        }
+    }
+}
+
+/// Check that an inherited descriptor is a listening stream socket, so a misconfigured unit
+/// fails at startup rather than on the first accept.
+fn check_listening(socket: SockRef<'_>) -> io::Result<()> {
+    let not_listening = |what: &str| {
+        let msg = format!("systemd passed fd {} that is {}", SD_LISTEN_FDS_START, what);
+        Err(io::Error::new(io::ErrorKind::InvalidInput, msg))
+    };
+    match socket.r#type() {
+        Ok(ty) if ty == Type::STREAM => {}
+        Ok(_) => return not_listening("not a stream socket"),
+        Err(err) => return not_listening(&format!("not a socket: {}", err)),
+    }
+    if !socket.is_listener()? {
+        return not_listening("not listening");
+    }
+    Ok(())
+}
+
+/// The socket passed by systemd socket activation, if this process was started that way.
+/// Clears the activation env vars so child processes don't also try to use the socket.
+fn systemd_fd() -> Option<RawFd> {
+    let pid = std::env::var("LISTEN_PID").ok();
+    let fds = std::env::var("LISTEN_FDS").ok();
+    for key in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(key);
+    }
+    let pid: u32 = pid?.parse().ok()?;
+    let fds: u32 = fds?.parse().ok()?;
+    if pid != std::process::id() || fds == 0 {
+        return None;
+    }
+    if fds > 1 {
+        tracing::warn!("systemd passed {} sockets, using the first", fds);
+    }
+    Some(SD_LISTEN_FDS_START)
+}
+
+/// An accepted connection on either kind of listener.
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl AsyncRead for Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(s) => Pin::new(s).poll_read(cx, buf),
+            Self::Unix(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(s) => Pin::new(s).poll_write(cx, buf),
+            Self::Unix(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Tcp(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+            Self::Unix(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Self::Tcp(s) => s.is_write_vectored(),
+            Self::Unix(s) => s.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(s) => Pin::new(s).poll_flush(cx),
+            Self::Unix(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Tcp(s) => Pin::new(s).poll_shutdown(cx),
+            Self::Unix(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::config::Config;
+use axum::{extract::ConnectInfo, http::Request, Router};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto::Builder,
+    service::TowerToHyperService,
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tower::ServiceExt;
+mod listener;
+pub use listener::Listener;
+mod tls;
+pub use tls::Tls;
+
+/// Time allowed for a client to complete the TLS handshake.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves the api over HTTP/1.1 and HTTP/2 on accepted connections.
+/// The protocol is detected per connection, so HTTP/2 works with prior knowledge (h2c)
+/// as well as when negotiated with ALPN over TLS.
+#[derive(Clone)]
+pub struct Server {
+    router: Router,
+    builder: Arc<Builder<TokioExecutor>>,
+}
+
+impl Server {
+    /// Create a server with protocol settings from config.
+    pub fn new(router: Router, config: &Config) -> Self {
+        let mut builder = Builder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .keep_alive(config.http1_keep_alive);
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .max_concurrent_streams(config.http2_max_concurrent_streams);
+        if config.http2_keep_alive_secs > 0 {
+            builder
+                .http2()
+                .keep_alive_interval(Duration::from_secs(config.http2_keep_alive_secs))
+                .keep_alive_timeout(Duration::from_secs(config.http2_keep_alive_timeout_secs));
+        }
+
+        Self {
+            router,
+            builder: Arc::new(builder),
+        }
+    }
+
+    /// Accept and serve connections until the process exits, over TLS when given.
+    /// Each TLS connection uses the certificates current when it was accepted.
+    pub async fn serve(&self, listener: Listener, tls: Option<Arc<Tls>>) {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    accept_error(err).await;
+                    continue;
+                }
+            };
+            match tls.as_ref() {
+                Some(tls) => {
+                    let acceptor = tls.acceptor();
+                    let server = self.clone();
+                    tokio::spawn(async move {
+                        let handshake = acceptor.accept(stream);
+                        match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, handshake).await {
+                            Ok(Ok(stream)) => server.spawn_connection(stream, peer),
+                            Ok(Err(err)) => tracing::debug!("TLS handshake failed: {}", err),
+                            Err(_) => tracing::debug!("TLS handshake timed out"),
+                        }
+                    });
+                }
+                None => self.spawn_connection(stream, peer),
+            }
+        }
+    }
+
+    /// Serve a single connection in the background.
+    /// Requests on tcp connections carry the peer address as `ConnectInfo<SocketAddr>`.
+    fn spawn_connection<I>(&self, io: I, peer: Option<SocketAddr>)
+    where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let builder = Arc::clone(&self.builder);
+        let router = self
+            .router
+            .clone()
+            .map_request(move |mut req: Request<Incoming>| {
+                if let Some(addr) = peer {
+                    req.extensions_mut().insert(ConnectInfo(addr));
+                }
+                req
+            });
+        let service = TowerToHyperService::new(router);
+        tokio::spawn(async move {
+            let conn = builder.serve_connection_with_upgrades(TokioIo::new(io), service);
+            if let Err(err) = conn.await {
+                tracing::debug!("connection error: {}", err);
+            }
+        });
+    }
+}
+
+/// Back off after accept errors, e.g. when out of file descriptors.
+async fn accept_error(err: std::io::Error) {
+    tracing::error!("accept error: {}", err);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+}
@@ -0,0 +1,153 @@
+use crate::config::Config;
+use rustls::{
+    pki_types::CertificateDer, server::WebPkiClientVerifier, RootCertStore, ServerConfig,
+};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_rustls::TlsAcceptor;
+
+/// Protocols offered with ALPN, preferring HTTP/2.
+const ALPN: &[&[u8]] = &[b"h2", b"http/1.1"];
+
+/// TLS settings that can be swapped for new certificates while the server runs.
+/// Connections keep the settings they were accepted with, so a reload drops nothing.
+pub struct Tls {
+    cert_path: String,
+    key_path: String,
+    client_ca_path: Option<String>,
+    current: RwLock<Arc<ServerConfig>>,
+}
+
+impl Tls {
+    /// Load certificates from the paths in config; `None` when TLS isn't configured.
+    pub fn from_config(config: &Config) -> Result<Option<Arc<Self>>, String> {
+        let (cert_path, key_path) = match (&config.tls_cert_path, &config.tls_key_path) {
+            (Some(cert), Some(key)) => (cert.clone(), key.clone()),
+            (None, None) => return Ok(None),
+            _ => return Err("TLS_CERT_PATH and TLS_KEY_PATH must be set together".into()),
+        };
+        let client_ca_path = config.tls_client_ca_path.clone();
+        let server_config = build(&cert_path, &key_path, client_ca_path.as_deref())?;
+        Ok(Some(Arc::new(Self {
+            cert_path,
+            key_path,
+            client_ca_path,
+            current: RwLock::new(Arc::new(server_config)),
+        })))
+    }
+
+    /// An acceptor using the current certificates.
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(Arc::clone(&self.current.read().unwrap()))
+    }
+
+    /// Reload certificates from disk, keeping the current ones if the new ones are invalid.
+    pub fn reload(&self) {
+        match build(
+            &self.cert_path,
+            &self.key_path,
+            self.client_ca_path.as_deref(),
+        ) {
+            Ok(server_config) => {
+                *self.current.write().unwrap() = Arc::new(server_config);
+                tracing::info!("reloaded TLS certificates");
+            }
+            Err(err) => tracing::error!("failed to reload TLS certificates: {}", err),
+        }
+    }
+
+    /// Reload on SIGHUP, and when any of the files change if `poll` is non-zero.
+    pub fn watch(self: &Arc<Self>, poll: Duration) {
+        let tls = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut hangup = signal(SignalKind::hangup()).expect("failed to listen for SIGHUP");
+            while hangup.recv().await.is_some() {
+                tls.reload();
+            }
+        });
+
+        if poll.is_zero() {
+            return;
+        }
+        let tls = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut modified = tls.modified();
+            let mut interval = tokio::time::interval(poll);
+            loop {
+                interval.tick().await;
+                let latest = tls.modified();
+                if latest != modified {
+                    modified = latest;
+                    tls.reload();
+                }
+            }
+        });
+    }
+
+    /// Modification times of the certificate files.
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        [
+            Some(&self.cert_path),
+            Some(&self.key_path),
+            self.client_ca_path.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+    }
+}
+
+/// Build server TLS settings, requiring client certificates when a client CA is given.
+fn build(
+    cert_path: &str,
+    key_path: &str,
+    client_ca_path: Option<&str>,
+) -> Result<ServerConfig, String> {
+    let certs = load_certs(cert_path)?;
+    let key = rustls_pemfile::private_key(&mut open(key_path)?)
+        .map_err(|err| format!("{}: {}", key_path, err))?
+        .ok_or_else(|| format!("{}: no private key found", key_path))?;
+
+    let builder = match client_ca_path {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(path)? {
+                roots
+                    .add(cert)
+                    .map_err(|err| format!("{}: {}", path, err))?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .build()
+                .map_err(|err| format!("{}: {}", path, err))?;
+            ServerConfig::builder().with_client_cert_verifier(verifier)
+        }
+        None => ServerConfig::builder().with_no_client_auth(),
+    };
+
+    let mut server_config = builder
+        .with_single_cert(certs, key)
+        .map_err(|err| format!("{}: {}", cert_path, err))?;
+    server_config.alpn_protocols = ALPN.iter().map(|p| p.to_vec()).collect();
+    Ok(server_config)
+}
+
+/// Read all certificates from a PEM file.
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = rustls_pemfile::certs(&mut open(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("{}: {}", path, err))?;
+    if certs.is_empty() {
+        return Err(format!("{}: no certificates found", path));
+    }
+    Ok(certs)
+}
+
+fn open(path: &str) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| format!("{}: {}", path, err))
+}